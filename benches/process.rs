@@ -0,0 +1,90 @@
+//! End-to-end `process_records` throughput over generated inputs, the baseline the
+//! performance work is measured against, and the account output with and without buffering.
+//! Run with `cargo bench`.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use mini_ledger::{
+    generate_transactions, process_records, write_accounts, write_transactions, Config,
+    GeneratorConfig, Ledger,
+};
+
+/// Write the generated input to a temporary file, `process_records` reads from a path.
+fn input_file(name: &str, config: &GeneratorConfig) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("mini_ledger_bench_{}.csv", name));
+    let file = File::create(&path).expect("temporary input is writable");
+    write_transactions(file, &generate_transactions(config)).expect("temporary input is writable");
+    path
+}
+
+fn bench_process_records(c: &mut Criterion) {
+    let deposit_heavy = GeneratorConfig {
+        clients: 1_000,
+        deposits: 90_000,
+        withdrawals: 9_000,
+        disputes: 500,
+        resolves: 250,
+        chargebacks: 250,
+    };
+    let dispute_heavy = GeneratorConfig {
+        clients: 1_000,
+        deposits: 40_000,
+        withdrawals: 5_000,
+        disputes: 30_000,
+        resolves: 15_000,
+        chargebacks: 10_000,
+    };
+    let mut group = c.benchmark_group("process_records");
+    group.sample_size(20);
+    for (name, config) in [
+        ("deposit_heavy", deposit_heavy),
+        ("dispute_heavy", dispute_heavy),
+    ] {
+        let rows = config.deposits
+            + config.withdrawals
+            + config.disputes
+            + config.resolves
+            + config.chargebacks;
+        group.throughput(Throughput::Elements(rows as u64));
+        let path = input_file(name, &config);
+        let path = path.to_str().expect("temporary path is utf-8");
+        group.bench_function(name, |b| {
+            b.iter(|| process_records(path, &Config::default()).expect("generated input is valid"))
+        });
+    }
+    group.finish();
+}
+
+/// Writing every possible client account to a file, unbuffered and through a `BufWriter`.
+fn bench_write_accounts(c: &mut Criterion) {
+    let mut ledger = Ledger::new(Config::default());
+    for record in generate_transactions(&GeneratorConfig::deposit_per_client(u16::MAX)) {
+        ledger.apply(record);
+    }
+    let accounts = ledger.accounts();
+    let path = std::env::temp_dir().join("mini_ledger_bench_output.csv");
+    let mut group = c.benchmark_group("write_accounts");
+    group.sample_size(20);
+    group.throughput(Throughput::Elements(accounts.len() as u64));
+    group.bench_function("unbuffered", |b| {
+        b.iter(|| {
+            let mut out = File::create(&path).expect("temporary output is writable");
+            write_accounts(&mut out, accounts.values()).expect("temporary output is writable");
+        })
+    });
+    group.bench_function("buffered", |b| {
+        b.iter(|| {
+            let file = File::create(&path).expect("temporary output is writable");
+            let mut out = BufWriter::new(file);
+            write_accounts(&mut out, accounts.values()).expect("temporary output is writable");
+            out.flush().expect("temporary output is writable");
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_process_records, bench_write_accounts);
+criterion_main!(benches);
@@ -0,0 +1,252 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::amount::Amount;
+use crate::transaction::{Transaction, TxType};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Account<A = f32> {
+    pub client: u16,
+    pub available: A,
+    pub held: A,
+    pub total: A,
+    pub locked: bool,
+    /// What locked the account, `None` while it is unlocked or when loaded without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock_reason: Option<LockReason>,
+}
+
+/// Operation that locked an account.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockReason {
+    /// A chargeback of a disputed transaction.
+    Chargeback,
+    /// An administrator froze the account with `Ledger::freeze`.
+    AdminFreeze,
+}
+
+impl LockReason {
+    /// Name of the reason as written in the `lock_reason` output column.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LockReason::Chargeback => "chargeback",
+            LockReason::AdminFreeze => "admin_freeze",
+        }
+    }
+}
+
+/// Writes out account data with 4 precision points.
+impl<A: Amount> Display for Account<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{},{},{},{},{}",
+            self.client,
+            self.available.format(),
+            self.held.format(),
+            self.total.format(),
+            self.locked
+        )
+    }
+}
+
+/// Whether a locked account still accepts deposits, withdrawals are always refused.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum LockPolicy {
+    /// Locked accounts are frozen for every operation.
+    #[default]
+    RejectDeposits,
+    /// Locked accounts accept deposits, i.e to settle a debt.
+    AllowDeposits,
+}
+
+/// What happens to an account total driven negative, i.e by a chargeback on spent funds.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum NegativeTotalPolicy {
+    /// Keep the true negative total.
+    #[default]
+    Allow,
+    /// Floor the total at zero, tracking the shortfall as a write-off.
+    FloorZeroWriteOff,
+}
+
+/// What resolving the dispute of a withdrawal does with the held funds.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum WithdrawalResolvePolicy {
+    /// Return the held funds to available, the balances are back to before the dispute.
+    #[default]
+    ReturnToAvailable,
+    /// The funds pending return were settled outside the ledger, the held amount leaves the total.
+    SettledExternally,
+}
+
+/// Rejection reason of a dispute overdrawing past the configured floor.
+pub(crate) const BELOW_MIN_AVAILABLE: &str = "dispute would push available below the minimum";
+
+/// Bounds a dispute must stay within, unbounded when `None`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DisputeLimits<A> {
+    /// Floor of the available funds once the amount is held.
+    pub(crate) min_available: Option<A>,
+    /// Ceiling of the held funds once the amount is held.
+    pub(crate) max_held: Option<A>,
+}
+
+/// Verify matching client id and non locked account for every operation.
+/// Rejected operations leave the account untouched and return the reason.
+impl<A: PartialEq> Account<A> {
+    /// Whether both accounts match on client, balances and `locked`, what a snapshot records.
+    /// The lock reason is left out, an account loaded from a snapshot has none.
+    pub fn same_balances(&self, other: &Account<A>) -> bool {
+        self.client == other.client
+            && self.available == other.available
+            && self.held == other.held
+            && self.total == other.total
+            && self.locked == other.locked
+    }
+}
+
+impl<A: Amount> Account<A> {
+    /// Add deposit amount to an Account, a locked account accepts it only under `AllowDeposits`.
+    pub(crate) fn deposit(
+        &mut self,
+        record: &Transaction<A>,
+        policy: LockPolicy,
+    ) -> Result<(), &'static str> {
+        let accepted = !self.locked || policy == LockPolicy::AllowDeposits;
+        if accepted && self.client == record.client {
+            self.available += record.amount.unwrap_or_default();
+            self.total += record.amount.unwrap_or_default();
+            return Ok(());
+        }
+        Err("account is locked or client does not match")
+    }
+    /// Deduct withdrawal amount and its fee from an Account.
+    /// Ignore withdrawal request from an account with insufficient available funds to cover both,
+    /// allowing `tolerance` of float representation error above the available balance.
+    pub(crate) fn withdrawal(
+        &mut self,
+        record: &Transaction<A>,
+        tolerance: A,
+    ) -> Result<(), &'static str> {
+        let debit = record.amount.unwrap_or_default() + record.fee.unwrap_or_default();
+        if self.available + tolerance >= debit && !self.locked && self.client == record.client {
+            self.available -= debit;
+            self.total -= debit;
+            return Ok(());
+        }
+        Err("insufficient funds, account is locked or client does not match")
+    }
+    /// Held funds from a disputed transaction, within the optional `limits`.
+    pub(crate) fn dispute(
+        &mut self,
+        record: &Transaction<A>,
+        limits: DisputeLimits<A>,
+    ) -> Result<(), &'static str> {
+        let disputable = matches!(record.r#type, TxType::Withdrawal | TxType::Deposit);
+        if disputable && !self.locked && self.client == record.client {
+            let amount = record.amount.unwrap_or_default();
+            let (Some(held), Some(available)) = (
+                self.held.checked_add(amount),
+                self.available.checked_sub(amount),
+            ) else {
+                return Err("held amount would overflow");
+            };
+            if limits.min_available.is_some_and(|min| available < min) {
+                return Err(BELOW_MIN_AVAILABLE);
+            }
+            if limits.max_held.is_some_and(|max| held > max) {
+                return Err("held amount would exceed the client deposits");
+            }
+            self.held = held;
+            self.available = available;
+            return Ok(());
+        }
+        Err("transaction cannot be disputed")
+    }
+    /// Add resolved amount from a resolved transaction, or drop it from the total when
+    /// `settled_externally`, i.e a withdrawal dispute settled outside the ledger.
+    pub(crate) fn resolve(
+        &mut self,
+        record: &Transaction<A>,
+        settled_externally: bool,
+    ) -> Result<(), &'static str> {
+        if record.r#type == TxType::Dispute && !self.locked && self.client == record.client {
+            let amount = record.amount.unwrap_or_default();
+            let (total, available) = if settled_externally {
+                (self.total.checked_sub(amount), Some(self.available))
+            } else {
+                (Some(self.total), self.available.checked_add(amount))
+            };
+            let (Some(held), Some(total), Some(available)) =
+                (self.held.checked_sub(amount), total, available)
+            else {
+                return Err("held amount would overflow");
+            };
+            self.held = held;
+            self.total = total;
+            self.available = available;
+            return Ok(());
+        }
+        Err("transaction is not disputed")
+    }
+    /// Add the balances of the same client account from another ledger,
+    /// the account stays locked if either was, for the first known reason.
+    pub(crate) fn combine(&mut self, other: &Account<A>) {
+        self.available += other.available;
+        self.held += other.held;
+        self.total += other.total;
+        self.locked |= other.locked;
+        self.lock_reason = self.lock_reason.or(other.lock_reason);
+    }
+    /// Lock the account on an administrator request, keeping the reason of an existing lock.
+    pub(crate) fn freeze(&mut self) {
+        if !self.locked {
+            self.lock_reason = Some(LockReason::AdminFreeze);
+        }
+        self.locked = true;
+    }
+    /// Write off a negative total back to zero, returning the amount written off.
+    pub(crate) fn write_off_negative_total(&mut self) -> A {
+        if self.total >= A::default() {
+            return A::default();
+        }
+        let written_off = A::default() - self.total;
+        self.total = A::default();
+        self.available += written_off;
+        written_off
+    }
+    /// Deduct a disputed transaction amount.
+    /// Accept only a transaction still disputed whose amount is covered by the held funds,
+    /// allowing `tolerance` of float representation error above the held balance,
+    /// else ignore request, i.e a transaction already resolved.
+    pub(crate) fn chargeback(
+        &mut self,
+        record: &Transaction<A>,
+        tolerance: A,
+    ) -> Result<(), &'static str> {
+        if record.r#type == TxType::Dispute && self.client == record.client {
+            let amount = record.amount.unwrap_or_default();
+            if self.held + tolerance < amount {
+                return Err("held funds do not cover the chargeback");
+            }
+            let (Some(total), Some(held)) = (
+                self.total.checked_sub(amount),
+                self.held.checked_sub(amount),
+            ) else {
+                return Err("held amount would overflow");
+            };
+            // an account already frozen keeps the reason it was first locked for.
+            if !self.locked {
+                self.lock_reason = Some(LockReason::Chargeback);
+            }
+            self.locked = true;
+            self.total = total;
+            self.held = held;
+            return Ok(());
+        }
+        Err("transaction is not disputed")
+    }
+}
@@ -0,0 +1,54 @@
+use crate::amount::Amount;
+use crate::transaction::{Transaction, TxType};
+
+/// Running totals of the transactions applied to a client account.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ClientActivity<A = f32> {
+    /// Sum of the deposited amounts.
+    pub deposited: A,
+    /// Sum of the withdrawn amounts.
+    pub withdrawn: A,
+    /// Number of deposits.
+    pub deposits: usize,
+    /// Number of withdrawals.
+    pub withdrawals: usize,
+    /// Number of disputes opened.
+    pub disputes: usize,
+    /// Number of chargebacks.
+    pub chargebacks: usize,
+}
+
+impl<A: Amount> ClientActivity<A> {
+    /// Account for an applied transaction.
+    pub(crate) fn record(&mut self, record: &Transaction<A>) {
+        let amount = record.amount.unwrap_or_default();
+        match record.r#type {
+            TxType::Deposit => {
+                self.deposited += amount;
+                self.deposits += 1;
+            }
+            TxType::Withdrawal => {
+                self.withdrawn += amount;
+                self.withdrawals += 1;
+            }
+            TxType::Dispute => self.disputes += 1,
+            TxType::Chargeback => self.chargebacks += 1,
+            TxType::Resolve | TxType::Unknown => {}
+        }
+    }
+
+    /// Add the totals of the same client from another ledger.
+    pub(crate) fn merge(&mut self, other: &ClientActivity<A>) {
+        self.deposited += other.deposited;
+        self.withdrawn += other.withdrawn;
+        self.deposits += other.deposits;
+        self.withdrawals += other.withdrawals;
+        self.disputes += other.disputes;
+        self.chargebacks += other.chargebacks;
+    }
+
+    /// Deposited minus withdrawn amounts.
+    pub fn net(&self) -> A {
+        self.deposited - self.withdrawn
+    }
+}
@@ -0,0 +1,269 @@
+use std::fmt::{Debug, Display};
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Arithmetic the ledger needs from an amount, so the precision backend can be swapped.
+pub trait Amount:
+    Copy
+    + Default
+    + PartialOrd
+    + Debug
+    + Display
+    + Add<Output = Self>
+    + AddAssign
+    + Sub<Output = Self>
+    + SubAssign
+    + Serialize
+    + DeserializeOwned
+{
+    /// Sum of both amounts, `None` when it cannot be represented.
+    fn checked_add(self, other: Self) -> Option<Self>;
+    /// Difference of both amounts, `None` when it cannot be represented.
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    /// Amount multiplied by `factor`, `None` when it cannot be represented.
+    fn checked_scale(self, factor: f32) -> Option<Self>;
+    /// Parses an amount string, surrounding whitespace is ignored, non finite values and
+    /// scientific notation are refused.
+    fn parse(value: &str) -> Result<Self, &'static str>;
+    /// Converts a threshold given as `f32`, i.e from the `Config`.
+    fn from_f32(value: f32) -> Self;
+    /// Whether the amount is neither NaN nor infinite.
+    fn is_finite(self) -> bool;
+    /// Converts the amount for display arithmetic, i.e scaling or rounding the output.
+    fn to_f64(self) -> f64;
+    /// Formats the amount with the 4 precision points of the output.
+    fn format(self) -> String {
+        format!("{:.4}", self)
+    }
+}
+
+/// Float amounts, arithmetic overflows to infinity instead of wrapping so only finite results are kept.
+macro_rules! float_amount {
+    ($float:ty) => {
+        impl Amount for $float {
+            fn checked_add(self, other: Self) -> Option<Self> {
+                Some(self + other).filter(|value| value.is_finite())
+            }
+
+            fn checked_sub(self, other: Self) -> Option<Self> {
+                Some(self - other).filter(|value| value.is_finite())
+            }
+
+            fn checked_scale(self, factor: f32) -> Option<Self> {
+                Some(self * factor as $float).filter(|value| value.is_finite())
+            }
+
+            fn parse(value: &str) -> Result<Self, &'static str> {
+                // real amounts are plain decimals, `1e3` is more likely a spreadsheet mangling.
+                if value.contains(['e', 'E']) {
+                    return Err("amount in scientific notation");
+                }
+                let amount: $float = value.trim().parse().map_err(|_| "amount is not a number")?;
+                if !amount.is_finite() {
+                    return Err("amount is not finite");
+                }
+                Ok(amount)
+            }
+
+            fn from_f32(value: f32) -> Self {
+                value as $float
+            }
+
+            fn is_finite(self) -> bool {
+                <$float>::is_finite(self)
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    };
+}
+
+float_amount!(f32);
+float_amount!(f64);
+
+/// Parses an `f32` amount string with `Amount::parse`, like the transaction amount columns.
+/// Snapshots are read with serde's number parsing instead, `FixedPoint` included, as they only
+/// hold balances this crate wrote.
+pub fn parse_amount(value: &str) -> Result<f32, &'static str> {
+    <f32 as Amount>::parse(value)
+}
+
+/// Decimal separator used by the amount column.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DecimalSeparator {
+    #[default]
+    Point,
+    /// `100,50` locales, only usable with a field delimiter other than a comma.
+    Comma,
+}
+
+/// Rewrite an amount to the plain decimal point form `Amount::parse` reads.
+/// Thousands grouping, a comma with `Point` and a point with `Comma`, is dropped when `grouped`
+/// is set and refused otherwise.
+pub fn normalize_amount(
+    value: &str,
+    separator: DecimalSeparator,
+    grouped: bool,
+) -> Result<String, &'static str> {
+    let (decimal, grouping) = match separator {
+        DecimalSeparator::Point => ('.', ','),
+        DecimalSeparator::Comma => (',', '.'),
+    };
+    let mut value = value.trim().to_string();
+    if grouped {
+        value.retain(|c| c != grouping);
+    } else if value.contains(grouping) {
+        return Err("amount is not a number");
+    }
+    Ok(value.replace(decimal, "."))
+}
+
+/// Parses an amount written with the given decimal separator.
+/// With `Comma` a decimal point is refused, as those locales use it to group thousands.
+pub fn parse_amount_with(value: &str, separator: DecimalSeparator) -> Result<f32, &'static str> {
+    parse_amount(&normalize_amount(value, separator, false)?)
+}
+
+/// Parses an amount written with the given decimal separator and thousands grouping, i.e `1,000.50`.
+pub fn parse_grouped_amount(value: &str, separator: DecimalSeparator) -> Result<f32, &'static str> {
+    parse_amount(&normalize_amount(value, separator, true)?)
+}
+
+/// Strip a leading currency symbol, i.e `$100.50`, surrounding whitespace is ignored.
+pub fn strip_currency_symbol<'a>(value: &'a str, symbol: &str) -> &'a str {
+    let value = value.trim();
+    value.strip_prefix(symbol).map_or(value, str::trim_start)
+}
+
+/// Parses an amount optionally prefixed by the given currency symbol.
+pub fn parse_currency_amount(value: &str, symbol: &str) -> Result<f32, &'static str> {
+    parse_amount(strip_currency_symbol(value, symbol))
+}
+
+/// Deserialize an optional amount column with `Amount::parse`, an empty field is `None`.
+pub(crate) fn deserialize_amount<'de, D: Deserializer<'de>, A: Amount>(
+    deserializer: D,
+) -> Result<Option<A>, D::Error> {
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(value) => A::parse(value).map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::ledger::{Config, Ledger};
+    use crate::output::write_accounts;
+    use crate::transaction::Transaction;
+
+    /// Output of the mixed input processed with amounts of type `A`.
+    fn mixed_output<A: Amount>() -> Result<String, Box<dyn Error>> {
+        let mut ledger = Ledger::<A>::with_config(Config::default());
+        ledger.process_file("src/tests/input/mixed.csv")?;
+        ledger.deposit(8, 100, A::parse("0.1")?);
+        ledger.withdrawal(8, 101, A::parse("0.0999")?);
+        let mut out = Vec::new();
+        write_accounts(&mut out, ledger.accounts().values())?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    #[test]
+    fn test_parser_matches_deserializer() -> Result<(), Box<dyn Error>> {
+        let values = [
+            "0.1",
+            " 2.5000 ",
+            "0.0001",
+            "100.0001",
+            "340282340000000000000000000000000000000",
+            "+7",
+            "-0.0",
+            ".5",
+            "9.99999999",
+        ];
+        for value in values {
+            let row = format!("type,client,tx,amount\ndeposit,1,1,{}", value);
+            let mut rdr = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(row.as_bytes());
+            let record: Transaction = rdr.deserialize().next().unwrap()?;
+            assert_eq!(record.amount, Some(parse_amount(value)?), "{}", value);
+        }
+        assert!(parse_amount("NaN").is_err());
+        assert!(parse_amount("inf").is_err());
+        assert!(parse_amount("1,5").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_scientific_notation_rejected() {
+        assert_eq!(parse_amount("1000"), Ok(1000.0));
+        for value in ["1e3", "1E3", "2.5e-1", " 1e3 "] {
+            assert_eq!(parse_amount(value), Err("amount in scientific notation"));
+        }
+        let row = "type,client,tx,amount\ndeposit,1,1,1e3";
+        let mut rdr = csv::ReaderBuilder::new().from_reader(row.as_bytes());
+        assert!(rdr.deserialize::<Transaction>().next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_decimal_comma() {
+        let comma = DecimalSeparator::Comma;
+        assert_eq!(parse_amount_with("100,50", comma), Ok(100.5));
+        assert_eq!(parse_amount_with(" 7 ", comma), Ok(7.0));
+        assert!(parse_amount_with("1.000,50", comma).is_err());
+        assert_eq!(parse_amount_with("2.25", DecimalSeparator::Point), Ok(2.25));
+    }
+
+    #[test]
+    fn test_parse_grouped_amount() {
+        assert_eq!(
+            parse_grouped_amount("1,000.50", DecimalSeparator::Point),
+            Ok(1000.5)
+        );
+        assert_eq!(
+            parse_grouped_amount("1.000,50", DecimalSeparator::Comma),
+            Ok(1000.5)
+        );
+        assert_eq!(
+            parse_grouped_amount("12", DecimalSeparator::Point),
+            Ok(12.0)
+        );
+        assert!(parse_amount_with("1,000.50", DecimalSeparator::Point).is_err());
+    }
+
+    #[test]
+    fn test_parse_currency_amount() -> Result<(), Box<dyn Error>> {
+        assert_eq!(parse_currency_amount("$100.50", "$"), Ok(100.5));
+        assert_eq!(parse_currency_amount(" $ 7 ", "$"), Ok(7.0));
+        assert_eq!(parse_currency_amount("2.5", "$"), Ok(2.5));
+        assert!(parse_currency_amount("€3", "$").is_err());
+        assert!(parse_amount("$100.50").is_err());
+
+        let input = "type,client,tx,amount\ndeposit,1,1,$100.50\n";
+        let mut ledger = Ledger::new(Config {
+            currency_symbol: Some("$".to_string()),
+            ..Config::default()
+        });
+        ledger.process_reader(input.as_bytes())?;
+        assert_eq!(ledger[&1].total, 100.5);
+        let mut ledger = Ledger::new(Config::default());
+        assert!(ledger.process_reader(input.as_bytes()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_amount_types_agree() -> Result<(), Box<dyn Error>> {
+        let output = mixed_output::<f32>()?;
+        assert_eq!(output, mixed_output::<f64>()?);
+        assert!(output.contains("8,0.0001,0.0000,0.0001,false"));
+        Ok(())
+    }
+}
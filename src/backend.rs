@@ -0,0 +1,144 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+
+#[cfg(feature = "csv")]
+use crate::error::LedgerError;
+use crate::fixed::FixedPoint;
+use crate::ledger::{Config, Ledger};
+use crate::output::write_accounts;
+
+/// Amount representation picked at runtime, i.e to compare the float drift of an input
+/// against exact fixed point.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum AmountBackend {
+    #[default]
+    F32,
+    F64,
+    /// `FixedPoint` ten-thousandths.
+    Fixed,
+}
+
+impl FromStr for AmountBackend {
+    type Err = String;
+
+    fn from_str(backend: &str) -> Result<Self, Self::Err> {
+        match backend {
+            "f32" => Ok(AmountBackend::F32),
+            "f64" => Ok(AmountBackend::F64),
+            "fixed" => Ok(AmountBackend::Fixed),
+            _ => Err(format!("unknown amount backend : {}", backend)),
+        }
+    }
+}
+
+/// Ledger over the `Amount` backend picked at runtime.
+#[derive(Debug)]
+pub enum DynLedger {
+    F32(Ledger<f32>),
+    F64(Ledger<f64>),
+    Fixed(Ledger<FixedPoint>),
+}
+
+impl DynLedger {
+    pub fn new(backend: AmountBackend, config: Config) -> DynLedger {
+        match backend {
+            AmountBackend::F32 => DynLedger::F32(Ledger::with_config(config)),
+            AmountBackend::F64 => DynLedger::F64(Ledger::with_config(config)),
+            AmountBackend::Fixed => DynLedger::Fixed(Ledger::with_config(config)),
+        }
+    }
+
+    pub fn backend(&self) -> AmountBackend {
+        match self {
+            DynLedger::F32(_) => AmountBackend::F32,
+            DynLedger::F64(_) => AmountBackend::F64,
+            DynLedger::Fixed(_) => AmountBackend::Fixed,
+        }
+    }
+
+    /// Number of accounts in the ledger.
+    pub fn len(&self) -> usize {
+        match self {
+            DynLedger::F32(ledger) => ledger.accounts().len(),
+            DynLedger::F64(ledger) => ledger.accounts().len(),
+            DynLedger::Fixed(ledger) => ledger.accounts().len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of transactions offered to the ledger, applied or not.
+    pub fn processed(&self) -> usize {
+        match self {
+            DynLedger::F32(ledger) => ledger.processed(),
+            DynLedger::F64(ledger) => ledger.processed(),
+            DynLedger::Fixed(ledger) => ledger.processed(),
+        }
+    }
+
+    /// Process the input files in order, like `Ledger::process_files`.
+    #[cfg(feature = "csv")]
+    pub fn process_files<S: AsRef<str>>(&mut self, files: &[S]) -> Result<(), LedgerError> {
+        match self {
+            DynLedger::F32(ledger) => ledger.process_files(files),
+            DynLedger::F64(ledger) => ledger.process_files(files),
+            DynLedger::Fixed(ledger) => ledger.process_files(files),
+        }
+    }
+
+    /// Writes every account like `write_accounts`, so the outputs of the backends can be diffed.
+    pub fn write_accounts<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match self {
+            DynLedger::F32(ledger) => write_accounts(out, ledger.accounts().values()),
+            DynLedger::F64(ledger) => write_accounts(out, ledger.accounts().values()),
+            DynLedger::Fixed(ledger) => write_accounts(out, ledger.accounts().values()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+
+    /// Output of the input processed with the given backend.
+    fn output(path: &str, backend: AmountBackend) -> Result<String, Box<dyn Error>> {
+        let mut ledger = DynLedger::new(backend, Config::default());
+        assert_eq!(ledger.backend(), backend);
+        ledger.process_files(&[path])?;
+        let mut out = Vec::new();
+        ledger.write_accounts(&mut out)?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    #[test]
+    fn test_backends_compared() -> Result<(), Box<dyn Error>> {
+        let mixed = "src/tests/input/mixed.csv";
+        let fixed = output(mixed, AmountBackend::Fixed)?;
+        assert_eq!(output(mixed, AmountBackend::F32)?, fixed);
+        assert_eq!(output(mixed, AmountBackend::F64)?, fixed);
+
+        // f32 cannot hold 123456.78, the drift shows in the 4 decimals of client 1.
+        let drift = "src/tests/input/drift.csv";
+        let fixed = output(drift, AmountBackend::Fixed)?;
+        let float = output(drift, AmountBackend::F32)?;
+        assert_eq!(output(drift, AmountBackend::F64)?, fixed);
+        let available = |output: &str, client: &str| -> f64 {
+            let row = output
+                .lines()
+                .find(|row| row.starts_with(client))
+                .expect("client row is written");
+            row.split(',').nth(1).unwrap().parse().unwrap()
+        };
+        assert_eq!(available(&fixed, "1,"), 123456.77);
+        let drifted = (available(&float, "1,") - available(&fixed, "1,")).abs();
+        assert!(drifted > 0.001 && drifted < 0.01, "{}", drifted);
+        assert_eq!(available(&float, "2,"), available(&fixed, "2,"));
+        assert_eq!("fixed".parse(), Ok(AmountBackend::Fixed));
+        assert!("decimal".parse::<AmountBackend>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+use std::error::Error;
+use std::fmt::Display;
+use std::io;
+
+/// Errors aborting the processing of an input.
+#[derive(Debug)]
+pub enum LedgerError {
+    /// The input has no header row.
+    EmptyInput,
+    /// The header row does not match the expected columns, only checked with `strict_schema`.
+    Schema {
+        missing: Vec<String>,
+        unexpected: Vec<String>,
+    },
+    /// A record grew past `max_record_size`, i.e after an unterminated quote.
+    RecordTooLarge {
+        /// Line the record starts at.
+        line: u64,
+    },
+    /// A quoted field is followed by more data before the delimiter, i.e `"100"junk`.
+    TrailingData {
+        /// Line the record starts at.
+        line: u64,
+    },
+    /// A row has a transaction type this version does not know, skipped in lenient mode.
+    UnknownType {
+        /// Line of the row.
+        line: u64,
+    },
+    /// Input expected to hold a single record, i.e by `Ledger::apply_raw`, holds another one.
+    MultipleRecords {
+        /// Line the second record starts at.
+        line: u64,
+    },
+    Io(io::Error),
+    #[cfg(feature = "csv")]
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
+}
+
+impl Display for LedgerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedgerError::EmptyInput => write!(f, "input is empty, expected a header row"),
+            LedgerError::Schema {
+                missing,
+                unexpected,
+            } => write!(
+                f,
+                "invalid header, missing columns {:?}, unexpected columns {:?}",
+                missing, unexpected
+            ),
+            LedgerError::RecordTooLarge { line } => write!(
+                f,
+                "record starting at line {} exceeds the maximum record size, is a quote unterminated ?",
+                line
+            ),
+            LedgerError::TrailingData { line } => {
+                write!(f, "record at line {} has data after a closing quote", line)
+            }
+            LedgerError::UnknownType { line } => {
+                write!(f, "record at line {} has an unknown transaction type", line)
+            }
+            LedgerError::MultipleRecords { line } => {
+                write!(f, "expected a single record, another starts at line {}", line)
+            }
+            LedgerError::Io(err) => write!(f, "{}", err),
+            #[cfg(feature = "csv")]
+            LedgerError::Csv(err) => write!(f, "{}", err),
+            LedgerError::Json(err) => write!(f, "{}", err),
+            #[cfg(feature = "sqlite")]
+            LedgerError::Sqlite(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl LedgerError {
+    /// Whether the error comes from reading the input rather than its content.
+    pub fn is_io(&self) -> bool {
+        match self {
+            LedgerError::Io(_) => true,
+            #[cfg(feature = "csv")]
+            LedgerError::Csv(err) => matches!(err.kind(), csv::ErrorKind::Io(_)),
+            LedgerError::Json(err) => err.is_io(),
+            #[cfg(feature = "sqlite")]
+            LedgerError::Sqlite(_) => false,
+            LedgerError::EmptyInput
+            | LedgerError::Schema { .. }
+            | LedgerError::RecordTooLarge { .. }
+            | LedgerError::TrailingData { .. }
+            | LedgerError::UnknownType { .. }
+            | LedgerError::MultipleRecords { .. } => false,
+        }
+    }
+}
+
+impl Error for LedgerError {}
+
+/// Io error payload of a reader enforcing the maximum record size.
+#[derive(Debug)]
+pub(crate) struct RecordLimitExceeded {
+    pub(crate) line: u64,
+}
+
+impl Display for RecordLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "record at line {} is too large", self.line)
+    }
+}
+
+impl Error for RecordLimitExceeded {}
+
+/// The exceeded record limit carried by an io error, if any.
+fn record_limit(err: &io::Error) -> Option<u64> {
+    let limit = err.get_ref()?.downcast_ref::<RecordLimitExceeded>()?;
+    Some(limit.line)
+}
+
+impl From<io::Error> for LedgerError {
+    fn from(err: io::Error) -> Self {
+        match record_limit(&err) {
+            Some(line) => LedgerError::RecordTooLarge { line },
+            None => LedgerError::Io(err),
+        }
+    }
+}
+
+#[cfg(feature = "csv")]
+impl From<csv::Error> for LedgerError {
+    fn from(err: csv::Error) -> Self {
+        if let csv::ErrorKind::Io(io) = err.kind() {
+            if let Some(line) = record_limit(io) {
+                return LedgerError::RecordTooLarge { line };
+            }
+        }
+        LedgerError::Csv(err)
+    }
+}
+
+impl From<serde_json::Error> for LedgerError {
+    fn from(err: serde_json::Error) -> Self {
+        LedgerError::Json(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for LedgerError {
+    fn from(err: rusqlite::Error) -> Self {
+        LedgerError::Sqlite(err)
+    }
+}
+
+/// Reasons an account cannot be closed.
+#[derive(Debug, PartialEq)]
+pub enum CloseError {
+    UnknownClient,
+    /// The account total is not zero.
+    NonZeroBalance,
+    /// Some of the client transactions are still disputed.
+    OpenDisputes,
+}
+
+impl Display for CloseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseError::UnknownClient => write!(f, "no account for this client"),
+            CloseError::NonZeroBalance => write!(f, "account total is not zero"),
+            CloseError::OpenDisputes => write!(f, "account has open disputes"),
+        }
+    }
+}
+
+impl Error for CloseError {}
+
+/// Reasons two ledgers cannot be merged.
+#[derive(Debug, PartialEq)]
+pub enum MergeError {
+    /// Clients held by both ledgers, refused under `MergePolicy::RejectOverlap`.
+    OverlappingClients(Vec<u16>),
+    /// Transactions applied by both ledgers, whose dispute states cannot be reconciled.
+    ConflictingTxs(Vec<u32>),
+}
+
+impl Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeError::OverlappingClients(clients) => {
+                write!(f, "clients {:?} are held by both ledgers", clients)
+            }
+            MergeError::ConflictingTxs(txs) => {
+                write!(f, "transactions {:?} were applied by both ledgers", txs)
+            }
+        }
+    }
+}
+
+impl Error for MergeError {}
@@ -0,0 +1,179 @@
+use std::fmt::Display;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::amount::Amount;
+
+/// Decimals kept by `FixedPoint`, the 4 precision points of the output.
+const DECIMALS: usize = 4;
+
+/// Units per whole amount.
+const SCALE: i64 = 10_000;
+
+/// Fixed-point amount counted in ten-thousandths, exact for every amount written with up to
+/// 4 decimals. Plain arithmetic saturates at the `i64` bounds, the checked methods refuse them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FixedPoint(i64);
+
+impl FixedPoint {
+    /// Amount from a number of ten-thousandths, i.e `FixedPoint::from_units(15_000)` for `1.5`.
+    pub fn from_units(units: i64) -> FixedPoint {
+        FixedPoint(units)
+    }
+
+    /// Number of ten-thousandths of the amount.
+    pub fn units(self) -> i64 {
+        self.0
+    }
+}
+
+/// Always written with the 4 decimals, whatever the requested precision.
+impl Display for FixedPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let (units, scale) = (self.0.unsigned_abs(), SCALE as u64);
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            units / scale,
+            units % scale,
+            width = DECIMALS
+        )
+    }
+}
+
+impl Add for FixedPoint {
+    type Output = FixedPoint;
+
+    fn add(self, other: FixedPoint) -> FixedPoint {
+        FixedPoint(self.0.saturating_add(other.0))
+    }
+}
+
+impl AddAssign for FixedPoint {
+    fn add_assign(&mut self, other: FixedPoint) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for FixedPoint {
+    type Output = FixedPoint;
+
+    fn sub(self, other: FixedPoint) -> FixedPoint {
+        FixedPoint(self.0.saturating_sub(other.0))
+    }
+}
+
+impl SubAssign for FixedPoint {
+    fn sub_assign(&mut self, other: FixedPoint) {
+        *self = *self - other;
+    }
+}
+
+/// Serialized as a number, like the float amounts.
+impl Serialize for FixedPoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.0 as f64 / SCALE as f64)
+    }
+}
+
+impl<'de> Deserialize<'de> for FixedPoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = f64::deserialize(deserializer)?;
+        let units = (value * SCALE as f64).round();
+        if !units.is_finite() || units.abs() >= i64::MAX as f64 {
+            return Err(serde::de::Error::custom("amount is out of range"));
+        }
+        Ok(FixedPoint(units as i64))
+    }
+}
+
+impl Amount for FixedPoint {
+    fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(FixedPoint)
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(FixedPoint)
+    }
+
+    fn checked_scale(self, factor: f32) -> Option<Self> {
+        let units = (self.0 as f64 * factor as f64).round();
+        (units.is_finite() && units.abs() < i64::MAX as f64).then_some(FixedPoint(units as i64))
+    }
+
+    /// Parses a plain decimal with an optional sign and up to 4 decimals, more are refused
+    /// rather than rounded.
+    fn parse(value: &str) -> Result<Self, &'static str> {
+        let value = value.trim();
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, value.strip_prefix('+').unwrap_or(value)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let all_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !all_digits(whole) || !all_digits(fraction)
+        {
+            return Err("amount is not a number");
+        }
+        if fraction.len() > DECIMALS {
+            return Err("amount has more than 4 decimals");
+        }
+        let whole: i64 = match whole {
+            "" => 0,
+            whole => whole.parse().map_err(|_| "amount is out of range")?,
+        };
+        let fraction: i64 = format!("{:0<width$}", fraction, width = DECIMALS)
+            .parse()
+            .map_err(|_| "amount is not a number")?;
+        let units = whole
+            .checked_mul(SCALE)
+            .and_then(|units| units.checked_add(fraction))
+            .ok_or("amount is out of range")?;
+        Ok(FixedPoint(if negative { -units } else { units }))
+    }
+
+    fn from_f32(value: f32) -> Self {
+        FixedPoint((value as f64 * SCALE as f64).round() as i64)
+    }
+
+    fn is_finite(self) -> bool {
+        true
+    }
+
+    fn to_f64(self) -> f64 {
+        self.0 as f64 / SCALE as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format() {
+        let parsed = |value| FixedPoint::parse(value).map(|amount| amount.to_string());
+        assert_eq!(parsed("100.5"), Ok("100.5000".to_string()));
+        assert_eq!(parsed(" +7 "), Ok("7.0000".to_string()));
+        assert_eq!(parsed(".25"), Ok("0.2500".to_string()));
+        assert_eq!(parsed("-0.0001"), Ok("-0.0001".to_string()));
+        assert_eq!(parsed("1.00005"), Err("amount has more than 4 decimals"));
+        for value in [
+            "",
+            ".",
+            "1e3",
+            "1.2.3",
+            "--1",
+            "NaN",
+            "99999999999999999999",
+        ] {
+            assert!(FixedPoint::parse(value).is_err(), "{}", value);
+        }
+        // every sum of 4-decimal amounts is exact.
+        let tenth = FixedPoint::parse("0.1").unwrap();
+        assert_eq!(tenth + tenth + tenth, FixedPoint::parse("0.3").unwrap());
+        assert_eq!(FixedPoint::from_f32(0.3).units(), 3_000);
+    }
+}
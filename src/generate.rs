@@ -0,0 +1,223 @@
+#[cfg(feature = "csv")]
+use std::io::Write;
+
+#[cfg(feature = "csv")]
+use crate::amount::Amount;
+#[cfg(feature = "csv")]
+use crate::error::LedgerError;
+use crate::transaction::{Transaction, TxType};
+
+/// Number of transactions of each type `generate_transactions` produces.
+/// Control transactions are capped by what they can reference, i.e at most one dispute per deposit.
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    /// Number of clients the transactions are spread over, at least one.
+    pub clients: u16,
+    pub deposits: usize,
+    pub withdrawals: usize,
+    pub disputes: usize,
+    pub resolves: usize,
+    pub chargebacks: usize,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            clients: 10,
+            deposits: 100,
+            withdrawals: 20,
+            disputes: 10,
+            resolves: 5,
+            chargebacks: 5,
+        }
+    }
+}
+
+impl GeneratorConfig {
+    /// Every deposit disputed, the first half resolved and the other half charged back,
+    /// exercising the whole dispute subsystem.
+    pub fn dispute_everything(clients: u16, deposits: usize, withdrawals: usize) -> Self {
+        GeneratorConfig {
+            clients,
+            deposits,
+            withdrawals,
+            disputes: deposits,
+            resolves: deposits / 2,
+            chargebacks: deposits - deposits / 2,
+        }
+    }
+
+    /// A single deposit for each client, with `u16::MAX` the largest account output possible.
+    pub fn deposit_per_client(clients: u16) -> Self {
+        GeneratorConfig {
+            clients,
+            deposits: clients as usize,
+            withdrawals: 0,
+            disputes: 0,
+            resolves: 0,
+            chargebacks: 0,
+        }
+    }
+}
+
+/// Synthetic transactions for tests and benchmarks, deterministic for a given config.
+/// Deposits come first, then withdrawals small enough to be covered, disputes of the first deposits,
+/// and resolves then chargebacks of the first disputes, so every reference is valid.
+pub fn generate_transactions(config: &GeneratorConfig) -> Vec<Transaction> {
+    let clients = config.clients.max(1);
+    let disputes = config.disputes.min(config.deposits);
+    let resolves = config.resolves.min(disputes);
+    let chargebacks = config.chargebacks.min(disputes - resolves);
+    let mut records = Vec::with_capacity(
+        config.deposits + config.withdrawals + disputes + resolves + chargebacks,
+    );
+    for index in 0..config.deposits {
+        let client = index as u16 % clients + 1;
+        let amount = 100.0 + (index % 100) as f32 + 0.25;
+        let tx = records.len() as u32 + 1;
+        records.push(Transaction::new(TxType::Deposit, client, tx, Some(amount)));
+    }
+    // withdrawals only target clients with a deposit, each deposit covering many of them.
+    for index in 0..config.withdrawals.min(config.deposits * 100) {
+        let client = (index % config.deposits) as u16 % clients + 1;
+        let tx = records.len() as u32 + 1;
+        records.push(Transaction::new(TxType::Withdrawal, client, tx, Some(1.0)));
+    }
+    let deposits = records[..disputes].to_vec();
+    for deposit in &deposits {
+        records.push(Transaction::new(
+            TxType::Dispute,
+            deposit.client,
+            deposit.tx,
+            None,
+        ));
+    }
+    for deposit in &deposits[..resolves] {
+        records.push(Transaction::new(
+            TxType::Resolve,
+            deposit.client,
+            deposit.tx,
+            None,
+        ));
+    }
+    for deposit in &deposits[resolves..resolves + chargebacks] {
+        records.push(Transaction::new(
+            TxType::Chargeback,
+            deposit.client,
+            deposit.tx,
+            None,
+        ));
+    }
+    records
+}
+
+/// Writes transactions as a `type,client,tx,amount` csv the ledger reads back.
+#[cfg(feature = "csv")]
+pub fn write_transactions<W: Write, A: Amount>(
+    out: W,
+    records: &[Transaction<A>],
+) -> Result<(), LedgerError> {
+    let mut wtr = csv::Writer::from_writer(out);
+    wtr.write_record(["type", "client", "tx", "amount"])?;
+    for record in records {
+        let r#type = match record.r#type {
+            TxType::Deposit => "deposit",
+            TxType::Withdrawal => "withdrawal",
+            TxType::Dispute => "dispute",
+            TxType::Resolve => "resolve",
+            TxType::Chargeback => "chargeback",
+            TxType::Unknown => "unknown",
+        };
+        let amount = record.amount.map(A::format).unwrap_or_default();
+        wtr.write_record([
+            r#type,
+            &record.client.to_string(),
+            &record.tx.to_string(),
+            &amount,
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::ledger::{Config, Ledger};
+
+    #[test]
+    fn test_generated_file_processes_cleanly() -> Result<(), Box<dyn Error>> {
+        let config = GeneratorConfig {
+            clients: 7,
+            deposits: 50,
+            withdrawals: 30,
+            disputes: 12,
+            resolves: 4,
+            chargebacks: 20,
+        };
+        let records = generate_transactions(&config);
+        // chargebacks are capped at the disputes left unresolved.
+        assert_eq!(records.len(), 50 + 30 + 12 + 4 + 8);
+
+        let mut csv = Vec::new();
+        write_transactions(&mut csv, &records)?;
+        let mut ledger = Ledger::new(Config {
+            strict: true,
+            ..Config::default()
+        });
+        ledger.process_reader(csv.as_slice())?;
+        let mut expected = Ledger::new(Config::default());
+        // every generated transaction references a valid one, so all of them apply.
+        assert_eq!(expected.apply_all(records), 104);
+        assert_eq!(ledger.accounts(), expected.accounts());
+        assert_eq!(ledger.accounts().len(), 7);
+        assert!(ledger.disputed_totals().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dispute_everything_conserves_funds() -> Result<(), Box<dyn Error>> {
+        let config = GeneratorConfig::dispute_everything(50, 2_000, 500);
+        let records = generate_transactions(&config);
+        let mut csv = Vec::new();
+        write_transactions(&mut csv, &records)?;
+        let mut ledger = Ledger::new(Config {
+            audit_balance: true,
+            ..Config::default()
+        });
+        ledger.process_reader(csv.as_slice())?;
+        assert_eq!(ledger.stats().applied, records.len());
+        assert!(ledger.imbalances().is_empty());
+
+        // funds only enter with deposits and leave with withdrawals and chargebacks.
+        let amount = |r#type: TxType| -> f64 {
+            records
+                .iter()
+                .filter(|record| record.r#type == r#type)
+                .map(|record| record.amount.unwrap_or_default() as f64)
+                .sum()
+        };
+        let charged_back: f64 = records[1_000..2_000]
+            .iter()
+            .map(|record| record.amount.unwrap_or_default() as f64)
+            .sum();
+        let expected = amount(TxType::Deposit) - amount(TxType::Withdrawal) - charged_back;
+        let accounts = ledger.accounts().values();
+        let total: f64 = accounts.clone().map(|account| account.total as f64).sum();
+        let held: f64 = accounts.clone().map(|account| account.held as f64).sum();
+        assert_eq!(total, expected);
+        assert_eq!(held, 0.0);
+        assert!(ledger.disputed_totals().is_empty());
+        for account in accounts {
+            assert_eq!(account.available, account.total - account.held);
+            // every client has a charged back deposit among the second half.
+            assert!(account.locked);
+        }
+        let stats = ledger.stats();
+        assert_eq!(stats.disputed_deposits, 2_000);
+        assert_eq!((stats.resolved, stats.charged_back), (1_000, 1_000));
+        Ok(())
+    }
+}
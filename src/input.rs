@@ -0,0 +1,1164 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::amount::{normalize_amount, strip_currency_symbol, Amount, DecimalSeparator};
+use crate::error::{LedgerError, RecordLimitExceeded};
+use crate::ledger::{Config, Ledger};
+use crate::meta::LedgerMeta;
+use crate::output::ACCOUNTS_HEADER;
+use crate::transaction::{Transaction, TxType};
+
+/// Column order of a headerless record passed to `Ledger::apply_raw`.
+const RAW_HEADER: [&str; 4] = ["type", "client", "tx", "amount"];
+
+/// Check the header holds exactly the `RAW_HEADER` columns, in any order.
+fn validate_header(headers: &csv::StringRecord) -> Result<(), LedgerError> {
+    let missing: Vec<String> = RAW_HEADER
+        .iter()
+        .filter(|column| !headers.iter().any(|header| header == **column))
+        .map(|column| column.to_string())
+        .collect();
+    let unexpected: Vec<String> = headers
+        .iter()
+        .filter(|header| !RAW_HEADER.contains(header))
+        .map(str::to_string)
+        .collect();
+    if missing.is_empty() && unexpected.is_empty() {
+        return Ok(());
+    }
+    Err(LedgerError::Schema {
+        missing,
+        unexpected,
+    })
+}
+
+/// Rename the header columns listed in `aliases` to the column they stand for,
+/// matching them against the lowercased header when `case_insensitive`.
+fn alias_headers(
+    headers: &csv::StringRecord,
+    aliases: &BTreeMap<String, String>,
+    case_insensitive: bool,
+) -> csv::StringRecord {
+    headers
+        .iter()
+        .map(|header| {
+            if case_insensitive {
+                header.to_lowercase()
+            } else {
+                header.to_string()
+            }
+        })
+        .map(|header| aliases.get(&header).cloned().unwrap_or(header))
+        .collect()
+}
+
+/// Lowercase a single field of the row, i.e a capitalized `Deposit` type.
+fn lowercase_field(row: &csv::StringRecord, column: usize) -> csv::StringRecord {
+    let mut rewritten: csv::StringRecord = row
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            if index == column {
+                field.to_lowercase()
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    rewritten.set_position(row.position().cloned());
+    rewritten
+}
+
+/// Log a row skipped in lenient mode.
+fn log_malformed(position: Option<&csv::Position>, reason: &str) {
+    let line = position.map_or(0, |position| position.line());
+    eprintln!("skipped malformed row at line {} : {}", line, reason);
+}
+
+/// Columns holding an amount.
+const AMOUNT_COLUMNS: [&str; 2] = ["amount", "fee"];
+
+/// Rewrite localized amount fields in the plain decimal point form the deserializer reads,
+/// dropping the leading `currency` symbol when given.
+/// Fields that do not normalize are kept as is and reported by the deserializer.
+fn normalize_amounts(
+    row: &csv::StringRecord,
+    columns: &[usize],
+    separator: DecimalSeparator,
+    grouped: bool,
+    currency: Option<&str>,
+) -> csv::StringRecord {
+    let mut rewritten: csv::StringRecord = row
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            if columns.contains(&index) {
+                let field = currency.map_or(field, |symbol| strip_currency_symbol(field, symbol));
+                normalize_amount(field, separator, grouped).unwrap_or_else(|_| field.to_string())
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    rewritten.set_position(row.position().cloned());
+    rewritten
+}
+
+/// Csv reader set up from the config, reading the header row when `has_headers`.
+/// The returned set is filled with the start lines of the records holding trailing data.
+fn configured_reader<R: Read>(
+    config: &Config,
+    reader: R,
+    has_headers: bool,
+) -> (csv::Reader<TrailingData<R>>, Rc<RefCell<BTreeSet<u64>>>) {
+    let delimiter = config.delimiter.unwrap_or(b',');
+    let trailing_data = Rc::new(RefCell::new(BTreeSet::new()));
+    let reader = TrailingData::new(reader, delimiter, Rc::clone(&trailing_data));
+    let rdr = csv::ReaderBuilder::new()
+        .has_headers(has_headers)
+        .trim(csv::Trim::All)
+        .comment(Some(b'#'))
+        .delimiter(delimiter)
+        .from_reader(reader);
+    (rdr, trailing_data)
+}
+
+/// Rewrites the rows the way the config reads them, then deserializes them against `headers`.
+struct RowParser {
+    headers: csv::StringRecord,
+    type_column: Option<usize>,
+    case_insensitive: bool,
+    amount_columns: Vec<usize>,
+    separator: DecimalSeparator,
+    grouped: bool,
+    currency: Option<String>,
+}
+
+impl RowParser {
+    fn new(config: &Config, headers: csv::StringRecord) -> RowParser {
+        let separator = config.decimal_separator;
+        let grouped = config.grouped_amounts;
+        let currency = config.currency_symbol.clone();
+        let localized = separator != DecimalSeparator::Point || grouped || currency.is_some();
+        let amount_columns = headers
+            .iter()
+            .enumerate()
+            .filter(|(_, header)| localized && AMOUNT_COLUMNS.contains(header))
+            .map(|(index, _)| index)
+            .collect();
+        RowParser {
+            type_column: headers.iter().position(|header| header == "type"),
+            headers,
+            case_insensitive: config.case_insensitive,
+            amount_columns,
+            separator,
+            grouped,
+            currency,
+        }
+    }
+
+    /// Whether the row has an empty type field.
+    fn blank_type(&self, row: &csv::StringRecord) -> bool {
+        self.type_column
+            .is_some_and(|column| row.get(column) == Some(""))
+    }
+
+    fn parse<A: Amount>(&self, row: csv::StringRecord) -> csv::Result<Transaction<A>> {
+        let row = match self.type_column {
+            Some(column) if self.case_insensitive => lowercase_field(&row, column),
+            _ => row,
+        };
+        let row = if self.amount_columns.is_empty() {
+            row
+        } else {
+            normalize_amounts(
+                &row,
+                &self.amount_columns,
+                self.separator,
+                self.grouped,
+                self.currency.as_deref(),
+            )
+        };
+        row.deserialize(Some(&self.headers))
+    }
+}
+
+/// Reader failing once a record grows past `max` bytes.
+/// Records end at newlines outside of quotes, so an unterminated quote is caught early.
+/// Quotes follow the csv rules, they only open at the start of a field and `""` inside
+/// quotes is an escaped quote.
+struct RecordLimit<R> {
+    inner: R,
+    max: usize,
+    delimiter: u8,
+    record_len: usize,
+    line: u64,
+    record_line: u64,
+    quoted: bool,
+    /// A quote ended the quoted part, unless the next byte is a quote too.
+    closed: bool,
+    field_start: bool,
+}
+
+impl<R: Read> RecordLimit<R> {
+    fn new(inner: R, max: usize, delimiter: u8) -> RecordLimit<R> {
+        RecordLimit {
+            inner,
+            max,
+            delimiter,
+            record_len: 0,
+            line: 1,
+            record_line: 1,
+            quoted: false,
+            closed: false,
+            field_start: true,
+        }
+    }
+}
+
+impl<R: Read> Read for RecordLimit<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        for &byte in &buf[..read] {
+            if self.quoted {
+                self.quoted = byte != b'"';
+                self.closed = byte == b'"';
+            } else if self.closed && byte == b'"' {
+                // an escaped quote, the field goes on.
+                self.quoted = true;
+                self.closed = false;
+            } else {
+                self.closed = false;
+                self.quoted = byte == b'"' && self.field_start;
+            }
+            self.field_start = !self.quoted && (byte == self.delimiter || byte == b'\n');
+            if byte == b'\n' {
+                self.line += 1;
+                if !self.quoted {
+                    self.record_len = 0;
+                    self.record_line = self.line;
+                    continue;
+                }
+            }
+            self.record_len += 1;
+            if self.record_len > self.max {
+                let line = self.record_line;
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    RecordLimitExceeded { line },
+                ));
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Reader noting the records holding data after the closing quote of a field, i.e `"100"junk`,
+/// which the csv parser would otherwise silently append to the field.
+/// Lines are counted from the first byte read, the same as the csv reader positions.
+struct TrailingData<R> {
+    inner: R,
+    delimiter: u8,
+    line: u64,
+    record_line: u64,
+    quoted: bool,
+    /// A quote just closed a field, only whitespace may precede the next delimiter.
+    closed: bool,
+    field_start: bool,
+    /// Start lines of the records with trailing data.
+    records: Rc<RefCell<BTreeSet<u64>>>,
+}
+
+impl<R: Read> TrailingData<R> {
+    fn new(inner: R, delimiter: u8, records: Rc<RefCell<BTreeSet<u64>>>) -> TrailingData<R> {
+        TrailingData {
+            inner,
+            delimiter,
+            line: 1,
+            record_line: 1,
+            quoted: false,
+            closed: false,
+            field_start: true,
+            records,
+        }
+    }
+}
+
+impl<R: Read> Read for TrailingData<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        for &byte in &buf[..read] {
+            if byte == b'\n' {
+                self.line += 1;
+            }
+            if self.quoted {
+                self.quoted = byte != b'"';
+                self.closed = byte == b'"';
+                continue;
+            }
+            if self.closed {
+                match byte {
+                    // an escaped quote, the field goes on.
+                    b'"' => {
+                        self.quoted = true;
+                        self.closed = false;
+                        continue;
+                    }
+                    b' ' | b'\t' | b'\r' => continue,
+                    _ if byte == self.delimiter || byte == b'\n' => self.closed = false,
+                    _ => {
+                        self.records.borrow_mut().insert(self.record_line);
+                        self.closed = false;
+                    }
+                }
+            }
+            if byte == b'\n' {
+                self.record_line = self.line;
+            }
+            self.quoted = byte == b'"' && self.field_start;
+            self.field_start = byte == self.delimiter || byte == b'\n';
+        }
+        Ok(read)
+    }
+}
+
+/// Opens the input file, `-` streams transactions from stdin instead.
+pub fn open_input(file: &str) -> io::Result<Box<dyn io::Read>> {
+    match file {
+        "-" => Ok(Box::new(io::stdin())),
+        _ => Ok(Box::new(File::open(file)?)),
+    }
+}
+
+/// Paths of the `.csv` files of the directory, in sorted filename order so a directory is
+/// always processed the same. Subdirectories are not walked.
+pub fn csv_files_in(dir: &str) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_csv = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+        if !is_csv || path.is_dir() {
+            continue;
+        }
+        match path.to_str() {
+            Some(file) => files.push(file.to_string()),
+            None => eprintln!("skipped file with a non utf-8 name : {}", path.display()),
+        }
+    }
+    files.sort_by(|a, b| Path::new(a).file_name().cmp(&Path::new(b).file_name()));
+    Ok(files)
+}
+
+/// Reads csv file, or stdin for `-`, and returns the ledger final state.
+pub fn process_records(csv: &str, config: &Config) -> Result<Ledger, LedgerError> {
+    let mut ledger = Ledger::new(config.clone());
+    ledger.process_file(csv)?;
+    Ok(ledger)
+}
+
+/// Called with the ledger once each input row is applied, i.e to write the finalized accounts.
+type AfterRow<'a, A> = dyn FnMut(&mut Ledger<A>) -> io::Result<()> + 'a;
+
+impl<A: Amount> Ledger<A> {
+    /// Reads csv file and applies every transaction in order, a directory is read with `process_dir`.
+    pub fn process_file(&mut self, csv: &str) -> Result<(), LedgerError> {
+        self.process_file_with(csv, &mut |_| Ok(()))?;
+        self.flush_deferred();
+        Ok(())
+    }
+
+    /// Reads every `.csv` file of the directory in sorted filename order, as with `process_files`.
+    pub fn process_dir(&mut self, dir: &str) -> Result<(), LedgerError> {
+        self.process_files(&csv_files_in(dir)?)
+    }
+
+    /// Reads every file in order into the same ledger, a control transaction deferred
+    /// with `defer_window` may reference a transaction of a later file.
+    /// With `skip_unreadable_files` an unreadable file is logged and the next one processed.
+    pub fn process_files<S: AsRef<str>>(&mut self, files: &[S]) -> Result<(), LedgerError> {
+        self.process_files_with(files, &mut |_| Ok(()))?;
+        self.flush_deferred();
+        Ok(())
+    }
+
+    /// Reads every file like `process_files`, calling `after_row` once each row is applied.
+    fn process_files_with<S: AsRef<str>>(
+        &mut self,
+        files: &[S],
+        after_row: &mut AfterRow<'_, A>,
+    ) -> Result<(), LedgerError> {
+        for file in files {
+            match self.process_file_with(file.as_ref(), after_row) {
+                Err(err) if err.is_io() && self.config().skip_unreadable_files => {
+                    eprintln!("skipped unreadable file {} : {}", file.as_ref(), err);
+                }
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a file or directory like `process_file`, calling `after_row` once each row is applied.
+    fn process_file_with(
+        &mut self,
+        csv: &str,
+        after_row: &mut AfterRow<'_, A>,
+    ) -> Result<(), LedgerError> {
+        if Path::new(csv).is_dir() {
+            return self.process_files_with(&csv_files_in(csv)?, after_row);
+        }
+        self.process_input_with(csv, open_input(csv)?, after_row)
+    }
+
+    /// Applies transactions as they are read until the reader reaches EOF.
+    /// A leading `#` comment line is parsed as the ledger metadata, later comment lines are skipped.
+    pub fn process_reader<R: io::Read>(&mut self, reader: R) -> Result<(), LedgerError> {
+        self.process_input_with("<reader>", reader, &mut |_| Ok(()))?;
+        self.flush_deferred();
+        Ok(())
+    }
+
+    /// Reads every file or directory in order like `process_files`, writing each account to
+    /// `out` as soon as `stream_accounts` finalizes it rather than once the input is over.
+    /// Returns the number of accounts written.
+    pub fn stream_files<S: AsRef<str>, W: Write>(
+        &mut self,
+        files: &[S],
+        out: &mut W,
+    ) -> Result<usize, LedgerError> {
+        writeln!(out, "{}", ACCOUNTS_HEADER)?;
+        let mut written = 0;
+        let mut write_finalized = |ledger: &mut Self| -> io::Result<()> {
+            for account in ledger.take_finalized() {
+                writeln!(out, "{:}", account)?;
+                written += 1;
+            }
+            Ok(())
+        };
+        self.process_files_with(files, &mut write_finalized)?;
+        self.flush_deferred();
+        self.finalize_remaining();
+        write_finalized(self)?;
+        Ok(written)
+    }
+
+    /// Applies the transactions of the input named `name` in the duplicate tx reports,
+    /// calling `after_row` once each row is applied. Deferred transactions are left to the caller.
+    fn process_input_with<R: io::Read>(
+        &mut self,
+        name: &str,
+        reader: R,
+        after_row: &mut AfterRow<'_, A>,
+    ) -> Result<(), LedgerError> {
+        let input = self.begin_input(name);
+        let delimiter = self.config().delimiter.unwrap_or(b',');
+        let reader: Box<dyn Read + '_> = match self.config().max_record_size {
+            Some(max) => Box::new(RecordLimit::new(reader, max, delimiter)),
+            None => Box::new(reader),
+        };
+        let mut reader = BufReader::new(reader);
+        // the metadata line is only peeked, the csv reader skips it as a comment while
+        // counting it, so the reported line numbers still match the file.
+        let buffered = reader.fill_buf()?;
+        if buffered.starts_with(b"#") {
+            let end = buffered
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map_or(buffered.len(), |end| end + 1);
+            if let Some(meta) = LedgerMeta::parse(&String::from_utf8_lossy(&buffered[..end])) {
+                self.set_meta(meta);
+            }
+        }
+        let (mut rdr, trailing_data) = configured_reader(self.config(), reader, true);
+        let raw_headers = rdr.headers()?.clone();
+        // a header-only input is a valid empty ledger, a missing header is not.
+        if raw_headers.is_empty() {
+            return Err(LedgerError::EmptyInput);
+        }
+        let case_insensitive = self.config().case_insensitive;
+        let headers = alias_headers(
+            &raw_headers,
+            &self.config().column_aliases,
+            case_insensitive,
+        );
+        if self.config().strict_schema {
+            validate_header(&headers)?;
+        }
+        let lenient = self.config().lenient;
+        let shard = self.config().shard;
+        let parser = RowParser::new(self.config(), headers);
+        for result in rdr.records() {
+            let row = match result {
+                Ok(row) => row,
+                // io errors leave the reader in an unknown state, they are always fatal.
+                Err(err) if lenient && !err.is_io_error() => {
+                    log_malformed(err.position(), &err.to_string());
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let line = row.position().map_or(0, csv::Position::line);
+            if trailing_data.borrow().contains(&line) {
+                if lenient {
+                    log_malformed(row.position(), "data after a closing quote");
+                    continue;
+                }
+                return Err(LedgerError::TrailingData { line });
+            }
+            // concatenated exports may repeat the header row mid-file.
+            if row == raw_headers {
+                log_malformed(row.position(), "repeated header row");
+                continue;
+            }
+            if lenient && parser.blank_type(&row) {
+                log_malformed(row.position(), "missing transaction type");
+                continue;
+            }
+            let position = row.position().cloned();
+            match parser.parse::<A>(row) {
+                // rows of clients owned by another worker are not this ledger's to apply.
+                Ok(record) if shard.is_some_and(|shard| !shard.owns(record.client)) => {}
+                Ok(record) if record.r#type == TxType::Unknown => {
+                    if !lenient {
+                        return Err(LedgerError::UnknownType { line });
+                    }
+                    log_malformed(position.as_ref(), "unknown transaction type");
+                }
+                Ok(record) => {
+                    if matches!(record.r#type, TxType::Deposit | TxType::Withdrawal) {
+                        self.record_origin(record.tx, input, line);
+                    }
+                    self.apply_at(record, Some(line));
+                    after_row(self)?;
+                }
+                Err(err) if lenient => log_malformed(position.as_ref(), &err.to_string()),
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves every disputed transaction listed in a csv with a `tx` column,
+    /// i.e settlement data, returning how many were resolved. Unknown txs are logged and skipped.
+    pub fn resolve_batch<R: io::Read>(&mut self, reader: R) -> Result<usize, LedgerError> {
+        #[derive(serde::Deserialize)]
+        struct Instruction {
+            tx: u32,
+        }
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut resolved = 0;
+        for result in rdr.deserialize() {
+            let Instruction { tx } = result?;
+            match self.transaction(tx).map(|record| record.client) {
+                Some(client) if self.resolve(client, tx) => resolved += 1,
+                Some(_) => {}
+                None => eprintln!("skipped resolve of tx {} : transaction not found", tx),
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Parses a single headerless `type,client,tx,amount` record, read with the configured
+    /// delimiter and amount format, and applies it. A record of a client owned by another
+    /// shard is not applied. More than one record is an error, nothing is applied then.
+    /// Malformed bytes are returned as an error and never panic, so arbitrary input can be fed in.
+    pub fn apply_raw(&mut self, bytes: &[u8]) -> Result<bool, LedgerError> {
+        let (mut rdr, trailing_data) = configured_reader(self.config(), bytes, false);
+        let mut row = csv::StringRecord::new();
+        if !rdr.read_record(&mut row)? {
+            return Err(LedgerError::EmptyInput);
+        }
+        let line = row.position().map_or(0, csv::Position::line);
+        let mut next = csv::StringRecord::new();
+        if rdr.read_record(&mut next)? {
+            let line = next.position().map_or(0, csv::Position::line);
+            return Err(LedgerError::MultipleRecords { line });
+        }
+        if trailing_data.borrow().contains(&line) {
+            return Err(LedgerError::TrailingData { line });
+        }
+        let headers = csv::StringRecord::from(RAW_HEADER.to_vec());
+        let record: Transaction<A> = RowParser::new(self.config(), headers).parse(row)?;
+        if record.r#type == TxType::Unknown {
+            return Err(LedgerError::UnknownType { line });
+        }
+        if self
+            .config()
+            .shard
+            .is_some_and(|shard| !shard.owns(record.client))
+        {
+            return Ok(false);
+        }
+        Ok(self.apply_at(record, Some(line)))
+    }
+
+    /// Parses a single csv data line, i.e a message from a broker, and applies it.
+    pub fn apply_line(&mut self, line: &str) -> Result<bool, LedgerError> {
+        self.apply_raw(line.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::ledger::{DuplicateTx, RowOrigin, Shard};
+    use crate::output::write_accounts;
+
+    #[test]
+    fn test_stream_flushes_on_eof() -> Result<(), Box<dyn Error>> {
+        let stream =
+            "type,client,tx,amount\ndeposit,1,1,10.0\ndeposit,2,2,5.0\nwithdrawal,1,3,2.5\n";
+        let mut ledger = Ledger::new(Config::default());
+        ledger.process_reader(stream.as_bytes())?;
+        let mut out = Vec::new();
+        write_accounts(&mut out, ledger.accounts().values())?;
+
+        let output = String::from_utf8(out)?;
+        let mut lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.remove(0), "client, available, held, total, locked");
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "1,7.5000,0.0000,7.5000,false",
+                "2,5.0000,0.0000,5.0000,false"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let result = process_records("src/tests/input/empty.csv", &Config::default());
+        assert!(matches!(result, Err(LedgerError::EmptyInput)));
+    }
+
+    #[test]
+    fn test_header_only_input() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/header_only.csv", &Config::default())?;
+        assert!(ledger.accounts().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reordered_columns() -> Result<(), Box<dyn Error>> {
+        // fields are matched by header name, column order does not matter.
+        let ledger = process_records("src/tests/input/reordered.csv", &Config::default())?;
+        assert_eq!(ledger[&1].available, 10.0);
+        assert_eq!(ledger[&1].held, 2.0);
+        assert_eq!(ledger[&1].total, 12.0);
+        assert_eq!(ledger[&2].total, 4.5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_raw() -> Result<(), Box<dyn Error>> {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.apply_raw(b"deposit, 1, 1, 10.0")?;
+        ledger.apply_raw(b"withdrawal,1,2,4.0\n")?;
+        ledger.apply_raw(b"dispute,1,2")?;
+        assert_eq!(ledger[&1].available, 2.0);
+        assert_eq!(ledger[&1].held, 4.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_raw_garbage() {
+        let mut ledger = Ledger::new(Config::default());
+        let garbage: [&[u8]; 6] = [
+            b"",
+            b"\xff\xfe\x00\x01",
+            b"deposit,1",
+            b"deposit,-1,1,1.0",
+            b"refund,1,1,1.0",
+            b"deposit,1,99999999999,abc\n\"unterminated",
+        ];
+        for bytes in garbage {
+            assert!(ledger.apply_raw(bytes).is_err());
+        }
+        assert!(ledger.accounts().is_empty());
+    }
+
+    #[test]
+    fn test_metadata_header() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/metadata.csv", &Config::default())?;
+        let meta = ledger.meta().expect("metadata line is parsed");
+        assert_eq!(meta.version, Some(1));
+        assert_eq!(meta.currency.as_deref(), Some("USD"));
+        assert_eq!(ledger[&1].total, 7.5);
+
+        let ledger = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        assert!(ledger.meta().is_none());
+
+        // the metadata line counts in the reported line numbers.
+        let input =
+            "# ledger-version: 1\ntype,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,5.0\n";
+        let mut ledger = Ledger::new(Config {
+            record_rejections: true,
+            ..Config::default()
+        });
+        ledger.process_reader(input.as_bytes())?;
+        assert_eq!(ledger.meta().and_then(|meta| meta.version), Some(1));
+        assert_eq!(ledger.rejections()[0].line, Some(4));
+        let input = "# ledger-version: 1\ntype,client,tx,amount\ndeposit,1,1,\"1\"junk\n";
+        let result = Ledger::new(Config::default()).process_reader(input.as_bytes());
+        assert!(matches!(result, Err(LedgerError::TrailingData { line: 3 })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_blank_type_skipped_in_lenient_mode() -> Result<(), Box<dyn Error>> {
+        let config = Config {
+            lenient: true,
+            ..Config::default()
+        };
+        let ledger = process_records("src/tests/input/blank_type.csv", &config)?;
+        assert_eq!(ledger[&1].available, 7.0);
+        assert_eq!(ledger[&1].total, 7.0);
+
+        let result = process_records("src/tests/input/blank_type.csv", &Config::default());
+        assert!(matches!(result, Err(LedgerError::Csv(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_header_skipped() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/duplicate_header.csv", &Config::default())?;
+        assert_eq!(ledger[&1].available, 6.0);
+        assert_eq!(ledger[&2].total, 3.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_unreadable_files() -> Result<(), Box<dyn Error>> {
+        let files = [
+            "src/tests/input/missing.csv",
+            "src/tests/input/withdrawal.csv",
+        ];
+        let mut ledger = Ledger::new(Config::default());
+        let result = ledger.process_files(&files);
+        assert!(matches!(result, Err(LedgerError::Io(_))));
+        assert!(ledger.accounts().is_empty());
+
+        let mut ledger = Ledger::new(Config {
+            skip_unreadable_files: true,
+            ..Config::default()
+        });
+        ledger.process_files(&files)?;
+        assert_eq!(ledger[&1].available, 10.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_line_matches_batch() -> Result<(), Box<dyn Error>> {
+        let batch = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        let input = std::fs::read_to_string("src/tests/input/mixed.csv")?;
+        let mut ledger = Ledger::new(Config::default());
+        for line in input.lines().skip(1) {
+            ledger.apply_line(line)?;
+        }
+        assert_eq!(ledger.accounts(), batch.accounts());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_line_uses_config() -> Result<(), Box<dyn Error>> {
+        let mut ledger = Ledger::new(Config {
+            delimiter: Some(b';'),
+            decimal_separator: DecimalSeparator::Comma,
+            grouped_amounts: true,
+            currency_symbol: Some("$".to_string()),
+            case_insensitive: true,
+            shard: Some(Shard {
+                workers: 2,
+                worker: 1,
+            }),
+            record_rejections: true,
+            ..Config::default()
+        });
+        assert!(ledger.apply_line("Deposit;1;1;$1.000,50")?);
+        assert!(!ledger.apply_line("deposit;2;2;5,0")?);
+        assert!(!ledger.apply_line("withdrawal;1;3;2000,0")?);
+        assert_eq!(ledger[&1].total, 1000.5);
+        assert!(!ledger.accounts().contains_key(&2));
+        assert_eq!(ledger.rejections()[0].line, Some(1));
+
+        // several records are refused as a whole.
+        let result = ledger.apply_raw(b"deposit;1;4;1,0\ndeposit;1;5;1,0");
+        assert!(matches!(
+            result,
+            Err(LedgerError::MultipleRecords { line: 2 })
+        ));
+        assert_eq!(ledger[&1].total, 1000.5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decimal_comma_input() -> Result<(), Box<dyn Error>> {
+        let config = Config {
+            delimiter: Some(b';'),
+            decimal_separator: DecimalSeparator::Comma,
+            ..Config::default()
+        };
+        let ledger = process_records("src/tests/input/decimal_comma.csv", &config)?;
+        assert_eq!(ledger[&1].available, 100.5);
+        assert_eq!(ledger[&1].held, 0.25);
+        assert_eq!(ledger[&1].total, 100.75);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_schema() -> Result<(), Box<dyn Error>> {
+        let config = Config {
+            strict_schema: true,
+            ..Config::default()
+        };
+        let result = process_records("src/tests/input/missing_amount.csv", &config);
+        let Err(err @ LedgerError::Schema { .. }) = result else {
+            panic!("expected a schema error");
+        };
+        assert_eq!(
+            err.to_string(),
+            "invalid header, missing columns [\"amount\"], unexpected columns [\"note\"]"
+        );
+        // column order does not matter.
+        process_records("src/tests/input/reordered.csv", &config)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_grouped_amounts_input() -> Result<(), Box<dyn Error>> {
+        let config = Config {
+            delimiter: Some(b';'),
+            grouped_amounts: true,
+            ..Config::default()
+        };
+        let ledger = process_records("src/tests/input/grouped.csv", &config)?;
+        assert_eq!(ledger[&1].available, 1000.5);
+        assert_eq!(ledger[&1].total, 1000.5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_too_large() {
+        let mut input = "type,client,tx,amount\ndeposit,1,1,10.0\ndeposit,1,2,\"".to_string();
+        input.push_str(&"x\n".repeat(100_000));
+        let mut ledger = Ledger::new(Config {
+            max_record_size: Some(1024),
+            lenient: true,
+            ..Config::default()
+        });
+        let result = ledger.process_reader(input.as_bytes());
+        assert!(matches!(
+            result,
+            Err(LedgerError::RecordTooLarge { line: 3 })
+        ));
+
+        // quoted newlines are part of a record, well-formed inputs are unaffected.
+        let mut ledger = Ledger::new(Config {
+            max_record_size: Some(64),
+            ..Config::default()
+        });
+        let quoted = "type,client,tx,amount,memo\ndeposit,1,1,1.0,\"two\nlines\"\n";
+        assert!(ledger.process_reader(quoted.as_bytes()).is_ok());
+        assert_eq!(ledger[&1].total, 1.0);
+
+        // a quote inside a field is a plain character, escaped quotes do not close the field.
+        let mut ledger = Ledger::new(Config {
+            max_record_size: Some(64),
+            ..Config::default()
+        });
+        let mut memos = "type,client,tx,amount,memo\ndeposit,1,1,1.0,6\" pipe\n".to_string();
+        memos.push_str("deposit,1,2,1.0,\"a \"\"quoted\"\" word\"\n");
+        for tx in 3..20 {
+            memos.push_str(&format!("deposit,1,{tx},1.0,\n"));
+        }
+        assert!(ledger.process_reader(memos.as_bytes()).is_ok());
+        assert_eq!(ledger[&1].total, 19.0);
+    }
+
+    #[test]
+    fn test_resolve_batch() -> Result<(), Box<dyn Error>> {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 5.0);
+        ledger.deposit(2, 3, 4.0);
+        ledger.dispute(1, 1);
+        ledger.dispute(1, 2);
+        ledger.dispute(2, 3);
+
+        let resolved = ledger.resolve_batch(open_input("src/tests/input/resolve_batch.csv")?)?;
+        assert_eq!(resolved, 2);
+        assert_eq!(ledger[&1].available, 10.0);
+        assert_eq!(ledger[&1].held, 5.0);
+        assert_eq!(ledger[&2].available, 4.0);
+        assert_eq!(ledger[&2].held, 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shards_merge_to_full_result() -> Result<(), Box<dyn Error>> {
+        let full = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        let mut merged = BTreeMap::new();
+        for worker in 0..2 {
+            let config = Config {
+                shard: Some(Shard { workers: 2, worker }),
+                ..Config::default()
+            };
+            let shard = process_records("src/tests/input/mixed.csv", &config)?;
+            assert!(shard.accounts().keys().all(|client| client % 2 == worker));
+            merged.extend(shard.accounts().clone());
+        }
+        assert_eq!(&merged, full.accounts());
+        assert_eq!(
+            "1/4".parse(),
+            Ok(Shard {
+                workers: 4,
+                worker: 1
+            })
+        );
+        assert!("4/4".parse::<Shard>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailing_data_after_quote() -> Result<(), Box<dyn Error>> {
+        let input = "type,client,tx,amount,memo\n\
+                     deposit,1,1,\"100\"junk,\n\
+                     deposit,1,2,\"5.0\" ,\"say \"\"hi\"\"\"\n";
+        let mut ledger = Ledger::new(Config::default());
+        let result = ledger.process_reader(input.as_bytes());
+        let Err(err @ LedgerError::TrailingData { line: 2 }) = result else {
+            panic!("expected a trailing data error at line 2");
+        };
+        assert_eq!(
+            err.to_string(),
+            "record at line 2 has data after a closing quote"
+        );
+        assert!(ledger.accounts().is_empty());
+
+        let mut ledger = Ledger::new(Config {
+            lenient: true,
+            ..Config::default()
+        });
+        ledger.process_reader(input.as_bytes())?;
+        // whitespace after a closing quote and escaped quotes are not trailing data.
+        assert_eq!(ledger[&1].total, 5.0);
+        let memo = ledger
+            .transaction(2)
+            .and_then(|record| record.memo.as_deref());
+        assert_eq!(memo, Some("say \"hi\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_tx_across_files() -> Result<(), Box<dyn Error>> {
+        let files = ["src/tests/input/merge_a.csv", "src/tests/input/merge_b.csv"];
+        let mut ledger = Ledger::new(Config::default());
+        ledger.process_files(&files)?;
+        // client 1 rows in both files and the dispute of tx 1 are not duplicates.
+        assert_eq!(
+            ledger.duplicate_txs(),
+            &[DuplicateTx {
+                tx: 2,
+                first: RowOrigin {
+                    input: files[0].to_string(),
+                    line: 3,
+                },
+                duplicate: RowOrigin {
+                    input: files[1].to_string(),
+                    line: 4,
+                },
+            }]
+        );
+        assert_eq!(ledger[&1].held, 10.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_aliases() -> Result<(), Box<dyn Error>> {
+        let input = "type,account,id,amount\n\
+                     deposit,1,1,10.0\n\
+                     deposit,1,2,5.0\n\
+                     type,account,id,amount\n\
+                     dispute,1,1,\n";
+        let aliases = [("account", "client"), ("id", "tx")];
+        let mut ledger = Ledger::new(Config {
+            column_aliases: aliases
+                .iter()
+                .map(|(alias, column)| (alias.to_string(), column.to_string()))
+                .collect(),
+            strict_schema: true,
+            ..Config::default()
+        });
+        ledger.process_reader(input.as_bytes())?;
+        assert_eq!(ledger[&1].available, 5.0);
+        assert_eq!(ledger[&1].held, 10.0);
+        assert_eq!(ledger[&1].total, 15.0);
+
+        let mut ledger = Ledger::new(Config::default());
+        assert!(ledger.process_reader(input.as_bytes()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_type_skipped_in_lenient_mode() -> Result<(), Box<dyn Error>> {
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,10.0\n\
+                     freeze,1,2,\n\
+                     withdrawal,1,3,4.0\n";
+        let mut ledger = Ledger::new(Config {
+            lenient: true,
+            ..Config::default()
+        });
+        ledger.process_reader(input.as_bytes())?;
+        assert_eq!(ledger[&1].available, 6.0);
+        assert!(!ledger[&1].locked);
+        assert_eq!(ledger.processed(), 2);
+
+        let mut ledger = Ledger::new(Config::default());
+        let result = ledger.process_reader(input.as_bytes());
+        assert!(matches!(result, Err(LedgerError::UnknownType { line: 3 })));
+
+        let mut ledger = Ledger::new(Config::default());
+        let record = Transaction::new(TxType::Unknown, 1, 1, Some(1.0));
+        assert!(!ledger.apply(record));
+        assert!(ledger.accounts().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_case_insensitive_header_and_types() -> Result<(), Box<dyn Error>> {
+        let input = "Type,Client,TX,Amount\n\
+                     Deposit,1,1,10.0\n\
+                     DEPOSIT,1,2,5.0\n\
+                     Dispute,1,1,\n";
+        let mut ledger = Ledger::new(Config {
+            case_insensitive: true,
+            ..Config::default()
+        });
+        ledger.process_reader(input.as_bytes())?;
+        assert_eq!(ledger[&1].available, 5.0);
+        assert_eq!(ledger[&1].held, 10.0);
+        assert_eq!(ledger[&1].total, 15.0);
+
+        let mut ledger = Ledger::new(Config::default());
+        assert!(ledger.process_reader(input.as_bytes()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_dir() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir().join(format!("mini_ledger_dir_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        // the withdrawal of the second file needs the deposit of the first.
+        std::fs::write(
+            dir.join("2_withdrawals.csv"),
+            "type,client,tx,amount\nwithdrawal,1,2,4.0\n",
+        )?;
+        std::fs::write(
+            dir.join("1_deposits.CSV"),
+            "type,client,tx,amount\ndeposit,1,1,10.0\n",
+        )?;
+        std::fs::write(dir.join("notes.txt"), "not a csv")?;
+        let dir = dir.to_str().expect("temp dir is utf-8");
+
+        let files = csv_files_in(dir)?;
+        let names: Vec<_> = files
+            .iter()
+            .filter_map(|file| Path::new(file).file_name()?.to_str())
+            .collect();
+        assert_eq!(names, ["1_deposits.CSV", "2_withdrawals.csv"]);
+        let ledger = process_records(dir, &Config::default());
+        std::fs::remove_dir_all(dir)?;
+        let ledger = ledger?;
+        assert_eq!(ledger[&1].available, 6.0);
+        assert_eq!(ledger.processed(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deferred_across_files() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir().join(format!("mini_ledger_defer_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        // the dispute of the first file references the deposit of the second.
+        let disputes = dir.join("1_disputes.csv");
+        let deposits = dir.join("2_deposits.csv");
+        std::fs::write(&disputes, "type,client,tx,amount\ndispute,1,1,\n")?;
+        std::fs::write(&deposits, "type,client,tx,amount\ndeposit,1,1,10.0\n")?;
+        let mut ledger = Ledger::new(Config {
+            defer_window: Some(5),
+            ..Config::default()
+        });
+        let processed =
+            ledger.process_files(&[disputes.to_string_lossy(), deposits.to_string_lossy()]);
+        std::fs::remove_dir_all(&dir)?;
+        processed?;
+        assert_eq!(ledger[&1].held, 10.0);
+        assert_eq!(ledger[&1].available, 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_sorted_accounts() -> Result<(), Box<dyn Error>> {
+        let path = "src/tests/input/sorted_by_client.csv";
+        let general = process_records(path, &Config::default())?;
+        let mut expected = Vec::new();
+        write_accounts(&mut expected, general.accounts().values())?;
+
+        let config = Config {
+            sorted_by_client: true,
+            stream_accounts: true,
+            ..Config::default()
+        };
+        let mut ledger = Ledger::new(config.clone());
+        let mut out = Vec::new();
+        assert_eq!(ledger.stream_files(&[path], &mut out)?, 4);
+        assert_eq!(String::from_utf8(out)?, String::from_utf8(expected)?);
+        assert!(ledger.accounts().is_empty());
+        assert_eq!(ledger.history_len(), 0);
+
+        // streamed accounts still count against the limit, and directories are read as usual.
+        let dir = std::env::temp_dir().join(format!("mini_ledger_stream_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::copy(path, dir.join("sorted.csv"))?;
+        let mut ledger = Ledger::new(Config {
+            max_accounts: Some(2),
+            ..config.clone()
+        });
+        let mut out = Vec::new();
+        let written = ledger.stream_files(&[dir.to_string_lossy()], &mut out);
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(written?, 2);
+        assert_eq!(String::from_utf8(out)?.lines().count(), 3);
+
+        // only the current client is retained, the previous ones wait to be taken.
+        let mut ledger = Ledger::new(config);
+        for client in 1..=100 {
+            ledger.deposit(client, client as u32, 1.0);
+            ledger.withdrawal(client, 1000 + client as u32, 0.5);
+            assert_eq!(ledger.accounts().len(), 1);
+            assert!(ledger.history_len() <= 2);
+            let finalized = ledger.take_finalized();
+            assert_eq!(finalized.len(), usize::from(client > 1));
+            if let Some(account) = finalized.first() {
+                assert_eq!(account.client, client - 1);
+            }
+        }
+        ledger.finalize_remaining();
+        assert_eq!(ledger.take_finalized()[0].total, 0.5);
+        Ok(())
+    }
+}
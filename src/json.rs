@@ -0,0 +1,59 @@
+use serde_json::Value;
+
+use crate::amount::Amount;
+use crate::error::LedgerError;
+use crate::ledger::Ledger;
+use crate::transaction::Transaction;
+
+/// Columns holding an amount, read from their string form like a csv field.
+const AMOUNT_FIELDS: [&str; 2] = ["amount", "fee"];
+
+impl<A: Amount> Ledger<A> {
+    /// Deserializes a json object with the csv column names into a transaction and applies it.
+    /// Numeric amounts are parsed from their decimal form, the same as csv amounts.
+    pub fn apply_json(&mut self, value: &Value) -> Result<bool, LedgerError> {
+        let mut value = value.clone();
+        if let Some(object) = value.as_object_mut() {
+            for field in AMOUNT_FIELDS {
+                if let Some(Value::Number(amount)) = object.get(field) {
+                    let amount = amount.to_string();
+                    object.insert(field.to_string(), Value::String(amount));
+                }
+            }
+        }
+        let record: Transaction<A> = serde_json::from_value(value)?;
+        Ok(self.apply(record))
+    }
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod tests {
+    use std::error::Error;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::ledger::Config;
+
+    #[test]
+    fn test_apply_json_matches_csv() -> Result<(), Box<dyn Error>> {
+        let mut json_ledger = Ledger::new(Config::default());
+        json_ledger
+            .apply_json(&json!({"type": "deposit", "client": 1, "tx": 1, "amount": 10.1}))?;
+        json_ledger
+            .apply_json(&json!({"type": "withdrawal", "client": 1, "tx": 2, "amount": "2.5"}))?;
+        json_ledger.apply_json(&json!({"type": "dispute", "client": 1, "tx": 2}))?;
+
+        let mut csv_ledger = Ledger::new(Config::default());
+        csv_ledger.apply_line("deposit,1,1,10.1")?;
+        csv_ledger.apply_line("withdrawal,1,2,2.5")?;
+        csv_ledger.apply_line("dispute,1,2,")?;
+        assert_eq!(json_ledger.accounts(), csv_ledger.accounts());
+        assert_eq!(json_ledger[&1].held, 2.5);
+
+        assert!(json_ledger
+            .apply_json(&json!({"type": "refund", "client": 1}))
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,2752 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::ops::Index;
+use std::str::FromStr;
+
+use serde::{Serialize, Serializer};
+
+use crate::account::{
+    Account, DisputeLimits, LockPolicy, NegativeTotalPolicy, WithdrawalResolvePolicy,
+    BELOW_MIN_AVAILABLE,
+};
+use crate::activity::ClientActivity;
+use crate::amount::{Amount, DecimalSeparator};
+use crate::error::{CloseError, MergeError};
+use crate::meta::LedgerMeta;
+use crate::percentile::AmountReservoir;
+use crate::stats::ProcessingStats;
+use crate::transaction::{Adjustment, Transaction, TxType};
+
+/// Processing limits applied while building the ledger.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    /// Maximum number of accounts the ledger may hold, unlimited when `None`.
+    pub max_accounts: Option<usize>,
+    /// Maximum number of transactions accepted from a single client, unlimited when `None`.
+    pub max_client_txs: Option<usize>,
+    /// Reject rows whose timestamp is earlier than the previous row's.
+    pub require_ordered: bool,
+    /// Keep every applied transaction in the audit trail.
+    pub record_audit_trail: bool,
+    /// Skip and log malformed rows instead of aborting the whole input.
+    pub lenient: bool,
+    /// Whether deposits into locked accounts are accepted.
+    pub lock_policy: LockPolicy,
+    /// Log and skip input files that cannot be read instead of aborting the remaining files.
+    pub skip_unreadable_files: bool,
+    /// Amount a withdrawal may exceed the available funds by, absorbing float rounding error.
+    pub withdrawal_tolerance: f32,
+    /// Amount a chargeback may exceed the held funds by, absorbing float rounding error.
+    pub chargeback_tolerance: f32,
+    /// Field delimiter of the csv input, a comma when `None`.
+    pub delimiter: Option<u8>,
+    /// Decimal separator of the amount column.
+    pub decimal_separator: DecimalSeparator,
+    /// Accept thousands grouping in the amount columns, i.e `1,000.50` under a non-comma delimiter.
+    pub grouped_amounts: bool,
+    /// Currency symbol stripped from the start of the amount columns, i.e `$` for `$100.50`.
+    pub currency_symbol: Option<String>,
+    /// Header names of the input mapped to the columns they stand for,
+    /// i.e `account` to `client`, applied before the rows are read.
+    pub column_aliases: BTreeMap<String, String>,
+    /// Read the header and the transaction types whatever their case, i.e `Type` and `Deposit`.
+    /// Column aliases are then matched against the lowercased header.
+    pub case_insensitive: bool,
+    /// Panic on the first account invariant violation, only checked in debug builds.
+    pub assert_invariants: bool,
+    /// Require the header to hold exactly the `type,client,tx,amount` columns, in any order.
+    pub strict_schema: bool,
+    /// Smallest accepted deposit amount, dust deposits below it are rejected.
+    pub min_deposit: Option<f32>,
+    /// Check after every transaction that the system-wide total moved by exactly its amount.
+    pub audit_balance: bool,
+    /// Floor of the available funds, disputes overdrawing past it are rejected and flagged.
+    pub min_available: Option<f32>,
+    /// Strict mode, reject as malformed the rows otherwise tolerated, i.e a dispute carrying an amount.
+    pub strict: bool,
+    /// Sample size of the deposit and withdrawal amounts kept for `Ledger::amount_percentile`,
+    /// no amounts are sampled when `None`.
+    pub amount_samples: Option<usize>,
+    /// Reject disputes that would hold more than the client ever deposited.
+    /// Balances loaded from a snapshot have no recorded deposits, so this is meant for full inputs.
+    pub cap_disputes_at_deposits: bool,
+    /// Largest csv record accepted in bytes, guarding against runaway unterminated quotes.
+    pub max_record_size: Option<usize>,
+    /// Whether a chargeback may leave a negative total or floors it at zero as a write-off.
+    pub negative_total_policy: NegativeTotalPolicy,
+    /// Apply only the clients assigned to this worker, the other rows of the input are skipped.
+    pub shard: Option<Shard>,
+    /// Reject deposits into an account with any open dispute until it is settled.
+    pub freeze_deposits_on_dispute: bool,
+    /// Whether resolving the dispute of a withdrawal returns the held funds to available.
+    pub withdrawal_resolve_policy: WithdrawalResolvePolicy,
+    /// Largest accepted deposit or withdrawal amount, larger ones are rejected as absurd.
+    pub max_tx_amount: Option<f32>,
+    /// Keep every rejected transaction with its reason, for `Ledger::rejections`.
+    pub record_rejections: bool,
+    /// Number of later rows a control transaction referencing an unknown tx waits for it
+    /// to appear, i.e a dispute reordered before its deposit. Rejected right away when `None`.
+    pub defer_window: Option<usize>,
+    /// Reject a transaction leaving any balance NaN or infinite, restoring the account,
+    /// so float overflow does not silently propagate through the rest of the input.
+    pub reject_non_finite: bool,
+    /// The input is sorted by client, rows of a client below the current one are rejected.
+    /// The history of a client is dropped once the input moves past it, except its open
+    /// disputes, so memory is bounded by the largest client. Tx ids reused by another client
+    /// are then no longer caught.
+    pub sorted_by_client: bool,
+    /// With `sorted_by_client`, move the account of a client out of the ledger with the rest
+    /// of its history once the input moves past it, to be taken with `take_finalized`.
+    /// The ledger then only holds the current client, so accounts can be written as they finalize.
+    pub stream_accounts: bool,
+}
+
+/// How `Ledger::merge_with` treats clients held by both ledgers.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MergePolicy {
+    /// Fail the merge, the ledgers are expected to hold disjoint clients, i.e shards.
+    #[default]
+    RejectOverlap,
+    /// Sum the balances of the client, locked if either account was.
+    CombineBalances,
+}
+
+/// Share of the clients processed by one of several workers reading the same input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shard {
+    /// Number of workers the clients are spread over.
+    pub workers: u16,
+    /// Index of this worker, below `workers`.
+    pub worker: u16,
+}
+
+impl Shard {
+    /// Whether the client is assigned to this worker, i.e `client % workers == worker`.
+    pub fn owns(&self, client: u16) -> bool {
+        client % self.workers.max(1) == self.worker
+    }
+}
+
+/// Parses `<worker>/<workers>`, i.e `0/4` for the first of four workers.
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(shard: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid shard, expected <worker>/<workers> : {}", shard);
+        let (worker, workers) = shard.split_once('/').ok_or_else(invalid)?;
+        let worker = worker.trim().parse().map_err(|_| invalid())?;
+        let workers = workers.trim().parse().map_err(|_| invalid())?;
+        if worker >= workers {
+            return Err(invalid());
+        }
+        Ok(Shard { workers, worker })
+    }
+}
+
+/// Rejection reason of a transaction whose type this version does not know.
+const UNKNOWN_TYPE: &str = "unknown transaction type";
+
+/// Largest rounding error tolerated by the invariant checks.
+const INVARIANT_TOLERANCE: f32 = 0.0001;
+
+/// Log a transaction or adjustment the ledger refused to apply.
+fn log_rejected(rejected: &Rejected, id: u32, client: u16, reason: &str) {
+    match rejected {
+        Rejected::Transaction(tx_type) => {
+            eprintln!("rejected {tx_type:?} tx {id} for client {client} : {reason}")
+        }
+        Rejected::Adjustment => {
+            eprintln!("rejected adjustment {id} for client {client} : {reason}")
+        }
+    }
+}
+
+/// Panic with context when an applied transaction or adjustment, described by `applied`,
+/// broke an account invariant. `before` is the account state prior to it, `None` for a new
+/// account, and `keeps_total` whether it may only move funds between available and held.
+fn assert_invariants<A: Amount>(
+    applied: &str,
+    before: Option<&Account<A>>,
+    after: &Account<A>,
+    keeps_total: bool,
+) {
+    let tolerance = A::from_f32(INVARIANT_TOLERANCE);
+    let distance = |a: A, b: A| if a > b { a - b } else { b - a };
+    let context = || format!("{applied} : {before:?} -> {after:?}");
+    assert!(
+        distance(after.total, after.available + after.held) <= tolerance,
+        "total is not available + held after {}",
+        context()
+    );
+    assert!(
+        after.held >= A::default(),
+        "negative held after {}",
+        context()
+    );
+    if let (Some(before), true) = (before, keeps_total) {
+        assert!(
+            distance(after.total, before.total) <= tolerance,
+            "dispute cycle changed the total after {}",
+            context()
+        );
+    }
+}
+
+/// Change of the system-wide total expected from an applied transaction.
+/// Disputes and resolves only move funds between available and held.
+fn expected_delta<A: Amount>(record: &Transaction<A>) -> A {
+    let amount = record.amount.unwrap_or_default();
+    match record.r#type {
+        TxType::Deposit => amount,
+        TxType::Withdrawal => A::default() - amount - record.fee.unwrap_or_default(),
+        TxType::Chargeback => A::default() - amount,
+        TxType::Dispute | TxType::Resolve | TxType::Unknown => A::default(),
+    }
+}
+
+/// What a rejection refused, a transaction of the given type or a manual adjustment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejected {
+    Transaction(TxType),
+    Adjustment,
+}
+
+/// Written as the lowercase transaction type, or `adjustment`.
+impl Serialize for Rejected {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Rejected::Transaction(r#type) => r#type.serialize(serializer),
+            Rejected::Adjustment => serializer.serialize_str("adjustment"),
+        }
+    }
+}
+
+/// Transaction or adjustment the ledger refused to apply, kept with `record_rejections`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Rejection {
+    /// Input line of the transaction, `None` when it was not read from a csv.
+    pub line: Option<u64>,
+    pub client: u16,
+    /// Tx id of the transaction, or id of the adjustment.
+    pub tx: u32,
+    pub r#type: Rejected,
+    pub reason: &'static str,
+}
+
+/// Position of a row in the processed inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowOrigin {
+    /// Name of the input, the file path or `<reader>` for `Ledger::process_reader`.
+    pub input: String,
+    pub line: u64,
+}
+
+/// Deposit or withdrawal reusing the tx id of an earlier row, possibly of another input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateTx {
+    pub tx: u32,
+    pub first: RowOrigin,
+    pub duplicate: RowOrigin,
+}
+
+/// Control transaction waiting for the transaction it references, with `defer_window`.
+#[derive(Debug)]
+struct Deferred<A> {
+    record: Transaction<A>,
+    /// Rows left before it is applied anyway and rejected.
+    rows_left: usize,
+    /// Input line it was read from, for its rejection.
+    line: Option<u64>,
+}
+
+/// Add new client to the ledger , only deposit tnx are valid.
+/// otherwise account is open with 0 funds.
+fn create_new_account<A: Amount>(record: &Transaction<A>) -> Account<A> {
+    let total = match record.r#type {
+        TxType::Deposit => record.amount.unwrap_or_default(),
+        _ => A::default(),
+    };
+    Account {
+        client: record.client,
+        available: total,
+        held: A::default(),
+        total,
+        locked: false,
+        lock_reason: None,
+    }
+}
+/// Client accounts together with the transaction history used to resolve disputes.
+/// Every map is ordered, so iterating the accounts, and the output, is reproducible.
+/// Amounts are `f32` unless another `Amount` is picked with `Ledger::with_config`.
+#[derive(Debug, Default)]
+pub struct Ledger<A = f32> {
+    accounts: BTreeMap<u16, Account<A>>,
+    tx_history: BTreeMap<u32, Transaction<A>>,
+    config: Config,
+    last_timestamp: Option<u64>,
+    /// Number of transactions applied per client, used to enforce `max_client_txs`.
+    client_tx_counts: BTreeMap<u16, usize>,
+    /// Every applied transaction in processing order, recorded when `record_audit_trail` is set.
+    audit_trail: Vec<Transaction<A>>,
+    /// Every posted adjustment in order.
+    adjustments: Vec<Adjustment<A>>,
+    /// Number of adjustments offered to `adjust`, posted or not, the last id handed out.
+    adjustment_ids: u32,
+    /// Metadata read from the input leading comment line.
+    meta: Option<LedgerMeta>,
+    /// Idempotency keys of the applied transactions.
+    seen_keys: BTreeSet<String>,
+    /// Totals of the applied transactions per client.
+    activity: BTreeMap<u16, ClientActivity<A>>,
+    /// System-wide total expected from the applied transactions, tracked with `audit_balance`.
+    expected_total: Option<A>,
+    /// Transactions after which the system-wide total did not match the expected one.
+    imbalances: Vec<u32>,
+    /// Clients with a dispute rejected for overdrawing past `min_available`.
+    flagged: BTreeSet<u16>,
+    /// Applied transactions per client in processing order.
+    client_logs: BTreeMap<u16, Vec<Transaction<A>>>,
+    /// Disputed deposits whose funds were already withdrawn, leaving a shortfall.
+    shortfall_disputes: Vec<u32>,
+    /// Sample of the applied amounts, kept when `amount_samples` is set.
+    amounts: Option<AmountReservoir<A>>,
+    /// Amount written off per client by flooring negative totals at zero.
+    write_offs: BTreeMap<u16, A>,
+    /// Counts of the transactions offered to `apply`, applied or not.
+    stats: ProcessingStats,
+    /// Rejected transactions in processing order, recorded when `record_rejections` is set.
+    rejections: Vec<Rejection>,
+    /// Input line of the transaction being applied, if read from a csv.
+    line: Option<u64>,
+    /// Control transactions waiting for their reference, in the order they were received.
+    deferred: VecDeque<Deferred<A>>,
+    /// Names of the inputs read so far, indexed by `tx_origins`.
+    inputs: Vec<String>,
+    /// Input index and line of the row each deposit and withdrawal tx id was first read from.
+    tx_origins: BTreeMap<u32, (usize, u64)>,
+    /// Deposits and withdrawals read with an already used tx id.
+    duplicate_txs: Vec<DuplicateTx>,
+    /// Client of the latest transaction, tracked with `sorted_by_client`.
+    current_client: Option<u16>,
+    /// Withdrawals currently under dispute, for `withdrawal_resolve_policy`.
+    disputed_withdrawals: BTreeSet<u32>,
+    /// Number of transactions currently under dispute per client.
+    open_disputes: BTreeMap<u16, usize>,
+    /// Clients of every transaction offered to `apply`, applied or not.
+    seen_clients: BTreeSet<u16>,
+    /// Clients with at least one applied transaction beyond opening an empty account.
+    transacted_clients: BTreeSet<u16>,
+    /// Accounts moved out of the ledger by `stream_accounts`, waiting for `take_finalized`.
+    finalized: Vec<Account<A>>,
+    /// Number of accounts ever finalized, still counted against `max_accounts`.
+    finalized_count: usize,
+}
+
+impl Ledger {
+    pub fn new(config: Config) -> Ledger {
+        Ledger::with_config(config)
+    }
+}
+
+impl<A: Amount> Ledger<A> {
+    /// Empty ledger over any `Amount`, i.e `Ledger::<f64>::with_config(config)`.
+    pub fn with_config(config: Config) -> Ledger<A> {
+        Ledger {
+            amounts: config.amount_samples.map(AmountReservoir::new),
+            config,
+            ..Ledger::default()
+        }
+    }
+
+    /// Start from previously reported account balances, i.e a loaded snapshot.
+    pub fn with_accounts(accounts: BTreeMap<u16, Account<A>>, config: Config) -> Ledger<A> {
+        Ledger {
+            accounts,
+            ..Ledger::with_config(config)
+        }
+    }
+
+    pub fn accounts(&self) -> &BTreeMap<u16, Account<A>> {
+        &self.accounts
+    }
+
+    pub fn audit_trail(&self) -> &[Transaction<A>] {
+        &self.audit_trail
+    }
+
+    pub fn activity(&self, client: u16) -> Option<&ClientActivity<A>> {
+        self.activity.get(&client)
+    }
+
+    /// Activity of every client with an applied transaction.
+    pub fn activities(&self) -> &BTreeMap<u16, ClientActivity<A>> {
+        &self.activity
+    }
+
+    /// Transactions flagged by `audit_balance` for creating or destroying money.
+    pub fn imbalances(&self) -> &[u32] {
+        &self.imbalances
+    }
+
+    /// Latest state of a transaction, a disputed deposit is stored as its dispute.
+    pub fn transaction(&self, tx: u32) -> Option<&Transaction<A>> {
+        self.tx_history.get(&tx)
+    }
+
+    /// Account state the chargeback of `tx` would leave, computed on a copy of the account so the
+    /// ledger is untouched. An undisputed deposit or withdrawal is disputed first, unbounded by
+    /// the dispute limits. `None` if the transaction is unknown, resolved, already charged back
+    /// or the chargeback would be rejected.
+    pub fn simulate_chargeback(&self, tx: u32) -> Option<Account<A>> {
+        let latest = self.tx_history.get(&tx)?;
+        let mut account = self.accounts.get(&latest.client)?.clone();
+        let disputed = match latest.r#type {
+            TxType::Dispute => latest.clone(),
+            TxType::Deposit | TxType::Withdrawal => {
+                let limits = DisputeLimits {
+                    min_available: None,
+                    max_held: None,
+                };
+                account.dispute(latest, limits).ok()?;
+                Transaction::new(TxType::Dispute, latest.client, tx, latest.amount)
+            }
+            _ => return None,
+        };
+        let tolerance = A::from_f32(self.config.chargeback_tolerance);
+        account.chargeback(&disputed, tolerance).ok()?;
+        if self.config.negative_total_policy == NegativeTotalPolicy::FloorZeroWriteOff {
+            account.write_off_negative_total();
+        }
+        Some(account)
+    }
+
+    /// Number of transactions kept in the history for disputes to reference.
+    pub fn history_len(&self) -> usize {
+        self.tx_history.len()
+    }
+
+    /// Every posted adjustment, in order.
+    pub fn adjustments(&self) -> &[Adjustment<A>] {
+        &self.adjustments
+    }
+
+    /// Adjustments posted to the client, in order.
+    pub fn client_adjustments(&self, client: u16) -> Vec<&Adjustment<A>> {
+        self.adjustments
+            .iter()
+            .filter(|adjustment| adjustment.client == client)
+            .collect()
+    }
+
+    /// Every applied transaction that touched the client, in processing order.
+    pub fn client_transactions(&self, client: u16) -> Vec<&Transaction<A>> {
+        self.client_logs
+            .get(&client)
+            .map(|log| log.iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Approximate `p`th percentile, 0 to 100, of the applied deposit and withdrawal amounts.
+    /// `None` unless `amount_samples` is set and some amounts were applied.
+    pub fn amount_percentile(&self, p: f64) -> Option<A> {
+        self.amounts.as_ref()?.percentile(p)
+    }
+
+    /// Disputes of deposits already spent, which drove the account available negative.
+    pub fn shortfall_disputes(&self) -> &[u32] {
+        &self.shortfall_disputes
+    }
+
+    /// Amount written off per client under `NegativeTotalPolicy::FloorZeroWriteOff`.
+    pub fn write_offs(&self) -> &BTreeMap<u16, A> {
+        &self.write_offs
+    }
+
+    /// Clients flagged for a dispute that would overdraw past `min_available`.
+    pub fn flagged(&self) -> &BTreeSet<u16> {
+        &self.flagged
+    }
+
+    /// Number of transactions processed, rejected ones included.
+    pub fn processed(&self) -> usize {
+        self.stats.processed
+    }
+
+    /// Applied and rejected counts, with the disputes broken down by referenced type and outcome.
+    pub fn stats(&self) -> &ProcessingStats {
+        &self.stats
+    }
+
+    /// Transactions refused so far with their reason, recorded when `record_rejections` is set.
+    pub fn rejections(&self) -> &[Rejection] {
+        &self.rejections
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn meta(&self) -> Option<&LedgerMeta> {
+        self.meta.as_ref()
+    }
+
+    #[cfg(feature = "csv")]
+    pub(crate) fn set_meta(&mut self, meta: LedgerMeta) {
+        self.meta = Some(meta);
+    }
+
+    /// Deposits and withdrawals whose tx id was already read, across every processed input.
+    pub fn duplicate_txs(&self) -> &[DuplicateTx] {
+        &self.duplicate_txs
+    }
+
+    /// Start reading a new input, returning its index for `record_origin`.
+    #[cfg(feature = "csv")]
+    pub(crate) fn begin_input(&mut self, name: &str) -> usize {
+        self.inputs.push(name.to_string());
+        self.inputs.len() - 1
+    }
+
+    /// Remember where a deposit or withdrawal tx id was read, reporting it if an earlier row used it.
+    #[cfg(feature = "csv")]
+    pub(crate) fn record_origin(&mut self, tx: u32, input: usize, line: u64) {
+        let Some(&(first_input, first_line)) = self.tx_origins.get(&tx) else {
+            self.tx_origins.insert(tx, (input, line));
+            return;
+        };
+        let duplicate = DuplicateTx {
+            tx,
+            first: RowOrigin {
+                input: self.inputs[first_input].clone(),
+                line: first_line,
+            },
+            duplicate: RowOrigin {
+                input: self.inputs[input].clone(),
+                line,
+            },
+        };
+        eprintln!(
+            "duplicate tx {} at {} line {}, first read at {} line {}",
+            tx,
+            duplicate.duplicate.input,
+            duplicate.duplicate.line,
+            duplicate.first.input,
+            duplicate.first.line
+        );
+        self.duplicate_txs.push(duplicate);
+    }
+
+    /// Apply a single transaction, rejected transactions leave the ledger untouched.
+    /// Returns whether the transaction was applied.
+    /// With `defer_window` a control transaction referencing an unknown tx is held back instead,
+    /// returning `false`, and retried as later rows come in.
+    pub fn apply(&mut self, record: Transaction<A>) -> bool {
+        let Some(window) = self.config.defer_window else {
+            return self.apply_now(record, true);
+        };
+        for deferred in &mut self.deferred {
+            deferred.rows_left = deferred.rows_left.saturating_sub(1);
+        }
+        let control = matches!(
+            record.r#type,
+            TxType::Dispute | TxType::Resolve | TxType::Chargeback
+        );
+        // a record out of client order is rejected right away rather than deferred.
+        let in_order = !self.config.sorted_by_client
+            || self
+                .current_client
+                .is_none_or(|current| record.client >= current);
+        let applied = if control && in_order && !self.tx_history.contains_key(&record.tx) {
+            self.deferred.push_back(Deferred {
+                record,
+                rows_left: window,
+                line: self.line,
+            });
+            false
+        } else {
+            self.apply_now(record, true)
+        };
+        self.retry_deferred(false);
+        applied
+    }
+
+    /// Apply the deferred transactions whose reference appeared, in the order they were received.
+    /// Those out of rows, or every one when `flush` is set, are applied anyway and rejected
+    /// if their reference is still unknown. Their client order was checked when they arrived.
+    fn retry_deferred(&mut self, flush: bool) {
+        let pending = std::mem::take(&mut self.deferred);
+        for deferred in pending {
+            let ready = self.tx_history.contains_key(&deferred.record.tx);
+            if ready || flush || deferred.rows_left == 0 {
+                let line = std::mem::replace(&mut self.line, deferred.line);
+                self.apply_now(deferred.record, false);
+                self.line = line;
+            } else {
+                self.deferred.push_back(deferred);
+            }
+        }
+    }
+
+    /// Apply every transaction still deferred, rejecting those whose reference never appeared.
+    /// Called once every input is fully read.
+    pub fn flush_deferred(&mut self) {
+        self.retry_deferred(true);
+    }
+
+    /// Apply the record, checking it keeps the input sorted by client when `check_order`.
+    fn apply_now(&mut self, mut record: Transaction<A>, check_order: bool) -> bool {
+        self.stats.processed += 1;
+        self.seen_clients.insert(record.client);
+        let mut successful = false;
+        if record.r#type == TxType::Unknown {
+            self.reject(&record, UNKNOWN_TYPE);
+            return false;
+        }
+        if self.config.sorted_by_client && check_order {
+            match self.current_client {
+                Some(current) if record.client < current => {
+                    self.reject(&record, "input not sorted by client");
+                    return false;
+                }
+                Some(current) if record.client > current => {
+                    self.prune_client_history(current);
+                    if self.config.stream_accounts {
+                        self.finalize_client(current);
+                    }
+                }
+                _ => {}
+            }
+            self.current_client = Some(record.client);
+        }
+        // redelivered transactions are dropped without touching the account.
+        if let Some(key) = &record.idempotency_key {
+            if self.seen_keys.contains(key) {
+                self.reject(&record, "idempotency key already applied");
+                return false;
+            }
+        }
+        // control transactions take their amount from the referenced transaction.
+        let control = matches!(
+            record.r#type,
+            TxType::Dispute | TxType::Resolve | TxType::Chargeback
+        );
+        if self.config.strict && control && record.amount.is_some() {
+            self.reject(&record, "malformed control transaction carrying an amount");
+            return false;
+        }
+        // rows without a timestamp are never considered out of order.
+        if let Some(timestamp) = record.timestamp {
+            if self.config.require_ordered
+                && self.last_timestamp.is_some_and(|last| timestamp < last)
+            {
+                self.reject(&record, "timestamp out of order");
+                return false;
+            }
+            self.last_timestamp = Some(timestamp);
+        }
+        if let Some(max) = self.config.max_client_txs {
+            let count = self.client_tx_counts.get(&record.client).copied();
+            if count.unwrap_or_default() >= max {
+                self.reject(&record, "maximum number of client transactions reached");
+                return false;
+            }
+        }
+        if let Some(min) = self.config.min_deposit {
+            let below = record.amount.unwrap_or_default() < A::from_f32(min);
+            if record.r#type == TxType::Deposit && below {
+                self.reject(&record, "deposit below the minimum amount");
+                return false;
+            }
+        }
+        if let Some(max) = self.config.max_tx_amount {
+            let above = record.amount.unwrap_or_default() > A::from_f32(max);
+            if matches!(record.r#type, TxType::Deposit | TxType::Withdrawal) && above {
+                self.reject(&record, "amount above the maximum transaction amount");
+                return false;
+            }
+        }
+        // a charged back id stays retired, reusing it would overwrite the chargeback in the history.
+        let charged_back = self
+            .tx_history
+            .get(&record.tx)
+            .is_some_and(|latest| latest.r#type == TxType::Chargeback);
+        if matches!(record.r#type, TxType::Deposit | TxType::Withdrawal) && charged_back {
+            self.reject(&record, "transaction id was charged back");
+            return false;
+        }
+        let frozen = self.config.freeze_deposits_on_dispute && self.has_open_dispute(record.client);
+        if record.r#type == TxType::Deposit && frozen {
+            self.reject(&record, "deposits are frozen while a dispute is open");
+            return false;
+        }
+        // refuse to open new accounts once the ledger is full, streamed accounts included.
+        if let Some(max) = self.config.max_accounts {
+            let opened = self.accounts.len() + self.finalized_count;
+            if opened >= max && !self.accounts.contains_key(&record.client) {
+                self.reject(&record, "maximum number of accounts reached");
+                return false;
+            }
+        }
+        if self.config.audit_balance && self.expected_total.is_none() {
+            self.expected_total = Some(self.system_total());
+        }
+        let total_before = self.client_total(record.client);
+        let mut opened = false;
+        let check_invariants = cfg!(debug_assertions) && self.config.assert_invariants;
+        let before = check_invariants
+            .then(|| self.accounts.get(&record.client).cloned())
+            .flatten();
+        // the account state to restore, `Some(None)` for an account opened by this transaction.
+        let guard_before = self
+            .config
+            .reject_non_finite
+            .then(|| self.accounts.get(&record.client).cloned());
+        let tx_history = &self.tx_history;
+        let lock_policy = self.config.lock_policy;
+        let tolerance = A::from_f32(self.config.withdrawal_tolerance);
+        let chargeback_tolerance = A::from_f32(self.config.chargeback_tolerance);
+        let deposited = self
+            .activity
+            .get(&record.client)
+            .map_or(A::default(), |activity| activity.deposited);
+        let limits = DisputeLimits {
+            min_available: self.config.min_available.map(A::from_f32),
+            max_held: self.config.cap_disputes_at_deposits.then_some(deposited),
+        };
+        let negative_total_policy = self.config.negative_total_policy;
+        let settled_externally = record.r#type == TxType::Resolve
+            && self.config.withdrawal_resolve_policy == WithdrawalResolvePolicy::SettledExternally
+            && self.disputed_withdrawals.contains(&record.tx);
+        let mut written_off = A::default();
+        let flagged = &mut self.flagged;
+        let mut rejection = None;
+        self.accounts
+            .entry(record.client)
+            .and_modify(|account| {
+                // fetch the referenced tx data for special tx type and verify the client id.
+
+                let transaction = match record.r#type {
+                    TxType::Dispute | TxType::Resolve | TxType::Chargeback => {
+                        tx_history.get(&record.tx)
+                    }
+                    TxType::Withdrawal | TxType::Deposit => Some(&record),
+                    TxType::Unknown => None,
+                };
+
+                if let Some(rc) = transaction {
+                    // match on incoming tx and use the correct tx data to process.
+                    let result = match record.r#type {
+                        TxType::Deposit => account.deposit(rc, lock_policy),
+                        TxType::Withdrawal => account.withdrawal(rc, tolerance),
+                        TxType::Dispute => account.dispute(rc, limits),
+                        TxType::Resolve => account.resolve(rc, settled_externally),
+                        TxType::Chargeback => account.chargeback(rc, chargeback_tolerance),
+                        TxType::Unknown => Err(UNKNOWN_TYPE),
+                    };
+                    if result.is_ok()
+                        && record.r#type == TxType::Chargeback
+                        && negative_total_policy == NegativeTotalPolicy::FloorZeroWriteOff
+                    {
+                        written_off = account.write_off_negative_total();
+                    }
+                    // need to update the tnx amount for tnx that is missing amount.
+                    record.amount = rc.amount;
+                    match result {
+                        Ok(()) => successful = true,
+                        Err(reason) => {
+                            if reason == BELOW_MIN_AVAILABLE {
+                                flagged.insert(record.client);
+                            }
+                            rejection = Some(reason);
+                        }
+                    }
+                } else {
+                    rejection = Some("referenced transaction not found");
+                }
+            })
+            .or_insert_with(|| {
+                // a new client has no history, control transactions can only reference later rows.
+                match record.r#type {
+                    TxType::Dispute | TxType::Resolve | TxType::Chargeback => {
+                        rejection = Some("referenced transaction not found")
+                    }
+                    TxType::Deposit | TxType::Withdrawal => successful = true,
+                    TxType::Unknown => rejection = Some(UNKNOWN_TYPE),
+                }
+                opened = true;
+                create_new_account(&record)
+            });
+        let finite = |account: &Account<A>| {
+            [account.available, account.held, account.total]
+                .into_iter()
+                .all(A::is_finite)
+        };
+        if let (true, Some(before)) = (successful, guard_before) {
+            if !self.accounts.get(&record.client).is_some_and(finite) {
+                match before {
+                    Some(before) => self.accounts.insert(record.client, before),
+                    None => self.accounts.remove(&record.client),
+                };
+                successful = false;
+                written_off = A::default();
+                rejection = Some("balance would not be finite");
+            }
+        }
+        if let Some(reason) = rejection {
+            self.reject(&record, reason);
+        }
+        // only update / insert successful transactions
+        if successful {
+            // only applied transactions count against the client limit.
+            if self.config.max_client_txs.is_some() {
+                *self.client_tx_counts.entry(record.client).or_default() += 1;
+            }
+            if check_invariants {
+                let applied = format!("{:?} tx {}", record.r#type, record.tx);
+                let keeps_total = matches!(record.r#type, TxType::Dispute | TxType::Resolve);
+                let after = &self.accounts[&record.client];
+                assert_invariants(&applied, before.as_ref(), after, keeps_total);
+            }
+            if let Some(key) = &record.idempotency_key {
+                self.seen_keys.insert(key.clone());
+            }
+            if self.config.audit_balance {
+                // a non deposit opening an account moves no funds.
+                let delta = if opened && record.r#type != TxType::Deposit {
+                    A::default()
+                } else {
+                    let settled = if settled_externally {
+                        record.amount.unwrap_or_default()
+                    } else {
+                        A::default()
+                    };
+                    expected_delta(&record) + written_off - settled
+                };
+                let moved = self.client_total(record.client) - total_before;
+                self.audit_balance(&record, moved, delta);
+            }
+            // the history still holds the disputed transaction at this point.
+            let account = &self.accounts[&record.client];
+            let referenced = self
+                .tx_history
+                .get(&record.tx)
+                .filter(|_| record.r#type == TxType::Dispute)
+                .map(|disputed| &disputed.r#type);
+            self.stats.record_applied(&record.r#type, referenced);
+            match (&record.r#type, referenced) {
+                (TxType::Dispute, Some(TxType::Withdrawal)) => {
+                    self.disputed_withdrawals.insert(record.tx);
+                }
+                (TxType::Resolve | TxType::Chargeback, _) => {
+                    self.disputed_withdrawals.remove(&record.tx);
+                }
+                _ => {}
+            }
+            match record.r#type {
+                TxType::Dispute => *self.open_disputes.entry(record.client).or_default() += 1,
+                TxType::Resolve | TxType::Chargeback => {
+                    if let Some(open) = self.open_disputes.get_mut(&record.client) {
+                        *open = open.saturating_sub(1);
+                    }
+                }
+                _ => {}
+            }
+            let disputed_deposit = referenced == Some(&TxType::Deposit);
+            if disputed_deposit && account.available < A::default() {
+                eprintln!(
+                    "dispute on spent funds, account now in shortfall : tx {} for client {} leaves available {}",
+                    record.tx,
+                    record.client,
+                    account.available.format()
+                );
+                self.shortfall_disputes.push(record.tx);
+            }
+            if written_off > A::default() {
+                *self.write_offs.entry(record.client).or_default() += written_off;
+            }
+            if let (Some(amounts), TxType::Deposit | TxType::Withdrawal, Some(amount)) =
+                (&mut self.amounts, &record.r#type, record.amount)
+            {
+                amounts.insert(amount);
+            }
+            let activity = self.activity.entry(record.client).or_default();
+            // only a deposit moves funds when opening the account.
+            if !opened || record.r#type == TxType::Deposit {
+                activity.record(&record);
+                self.transacted_clients.insert(record.client);
+            }
+            if self.config.record_audit_trail {
+                self.audit_trail.push(record.clone());
+            }
+            self.client_logs
+                .entry(record.client)
+                .or_default()
+                .push(record.clone());
+            self.tx_history.insert(record.tx, record);
+        }
+        successful
+    }
+
+    /// Log a refused transaction, keeping it for the warnings report with `record_rejections`.
+    fn reject(&mut self, record: &Transaction<A>, reason: &'static str) {
+        let rejected = Rejected::Transaction(record.r#type.clone());
+        self.reject_as(rejected, record.tx, record.client, reason);
+    }
+
+    /// Log, count and record the rejection of the transaction or adjustment `id`.
+    fn reject_as(&mut self, rejected: Rejected, id: u32, client: u16, reason: &'static str) {
+        log_rejected(&rejected, id, client, reason);
+        self.stats.rejected += 1;
+        if self.config.record_rejections {
+            self.rejections.push(Rejection {
+                line: self.line,
+                client,
+                tx: id,
+                r#type: rejected,
+                reason,
+            });
+        }
+    }
+
+    /// Apply a transaction read from the given input line, so its rejection can point back to it.
+    #[cfg(feature = "csv")]
+    pub(crate) fn apply_at(&mut self, record: Transaction<A>, line: Option<u64>) -> bool {
+        self.line = line;
+        let applied = self.apply(record);
+        self.line = None;
+        applied
+    }
+
+    /// Drop the history of a client the sorted input moved past, only its open disputes
+    /// can still be settled, by the held funds they keep.
+    fn prune_client_history(&mut self, client: u16) {
+        self.tx_history
+            .retain(|_, record| record.client != client || record.r#type == TxType::Dispute);
+        if let Some(log) = self.client_logs.get_mut(&client) {
+            let tx_history = &self.tx_history;
+            log.retain(|record| tx_history.contains_key(&record.tx));
+        }
+    }
+
+    /// Move the account of a client the sorted input moved past to the finalized ones,
+    /// dropping what is left of its history, its open disputes can no longer be settled.
+    fn finalize_client(&mut self, client: u16) {
+        self.tx_history.retain(|_, record| record.client != client);
+        self.client_logs.remove(&client);
+        self.open_disputes.remove(&client);
+        if let Some(account) = self.accounts.remove(&client) {
+            self.finalized.push(account);
+            self.finalized_count += 1;
+        }
+    }
+
+    /// Accounts finalized by `stream_accounts` since the last call, in client order.
+    pub fn take_finalized(&mut self) -> Vec<Account<A>> {
+        std::mem::take(&mut self.finalized)
+    }
+
+    /// Finalize every account still in the ledger, i.e the last client once the input is over.
+    pub fn finalize_remaining(&mut self) {
+        let clients: Vec<u16> = self.accounts.keys().copied().collect();
+        for client in clients {
+            self.finalize_client(client);
+        }
+    }
+
+    /// Whether any transaction of the client is currently disputed.
+    fn has_open_dispute(&self, client: u16) -> bool {
+        self.open_disputes
+            .get(&client)
+            .is_some_and(|open| *open > 0)
+    }
+
+    /// Sum of every account total.
+    fn system_total(&self) -> A {
+        self.accounts
+            .values()
+            .fold(A::default(), |sum, account| sum + account.total)
+    }
+
+    /// Total of the client account, zero when it does not exist.
+    fn client_total(&self, client: u16) -> A {
+        self.accounts
+            .get(&client)
+            .map_or(A::default(), |account| account.total)
+    }
+
+    /// Flag the transaction when the touched account total `moved` by other than `delta`,
+    /// then carry the system-wide total on by what actually moved so a violation is flagged once.
+    fn audit_balance(&mut self, record: &Transaction<A>, moved: A, delta: A) {
+        let difference = if moved > delta {
+            moved - delta
+        } else {
+            delta - moved
+        };
+        if difference > A::from_f32(INVARIANT_TOLERANCE) {
+            self.imbalances.push(record.tx);
+        }
+        self.expected_total = Some(self.expected_total.unwrap_or_default() + moved);
+    }
+
+    /// Apply every transaction in order, returning how many were applied.
+    /// The transactions still deferred are flushed once the records run out.
+    pub fn apply_all<I: IntoIterator<Item = Transaction<A>>>(&mut self, records: I) -> usize {
+        let applied = records
+            .into_iter()
+            .map(|record| self.apply(record))
+            .filter(|applied| *applied)
+            .count();
+        self.flush_deferred();
+        applied
+    }
+
+    /// Post a manual correction of the signed `delta` to the client available and total funds,
+    /// recorded with its `note` in the adjustment log, apart from the transactions.
+    /// A locked account only accepts credits under `LockPolicy::AllowDeposits`.
+    /// Adjustments take their ids from their own sequence, a dispute can never reference them.
+    /// Returns whether it was posted.
+    pub fn adjust(&mut self, client: u16, delta: A, note: &str) -> bool {
+        self.stats.processed += 1;
+        self.adjustment_ids += 1;
+        let id = self.adjustment_ids;
+        let credit = delta >= A::default();
+        let allow_deposits = self.config.lock_policy == LockPolicy::AllowDeposits;
+        let Some(before) = self.accounts.get(&client).cloned() else {
+            self.reject_as(Rejected::Adjustment, id, client, "unknown client");
+            return false;
+        };
+        if before.locked && !(credit && allow_deposits) {
+            self.reject_as(Rejected::Adjustment, id, client, "account is locked");
+            return false;
+        }
+        let (Some(available), Some(total)) = (
+            before.available.checked_add(delta),
+            before.total.checked_add(delta),
+        ) else {
+            self.reject_as(Rejected::Adjustment, id, client, "amount would overflow");
+            return false;
+        };
+        if self.config.reject_non_finite && !(available.is_finite() && total.is_finite()) {
+            let reason = "balance would not be finite";
+            self.reject_as(Rejected::Adjustment, id, client, reason);
+            return false;
+        }
+        let after = Account {
+            available,
+            total,
+            ..before.clone()
+        };
+        if cfg!(debug_assertions) && self.config.assert_invariants {
+            let applied = format!("adjustment {id}");
+            assert_invariants(&applied, Some(&before), &after, false);
+        }
+        let moved = after.total - before.total;
+        self.accounts.insert(client, after);
+        // the adjustment moves the system total by exactly its delta.
+        if let Some(expected) = &mut self.expected_total {
+            *expected += moved;
+        }
+        self.stats.applied += 1;
+        self.adjustments.push(Adjustment {
+            id,
+            client,
+            delta,
+            note: note.to_string(),
+        });
+        true
+    }
+
+    /// Zero the client balances and unlock the account, keeping it in the ledger.
+    /// Its history goes too, the disputes it held can no longer be settled.
+    /// Returns whether the account existed.
+    pub fn reset(&mut self, client: u16) -> bool {
+        self.forget_client(client);
+        match self.accounts.get_mut(&client) {
+            Some(account) => {
+                *account = Account {
+                    client,
+                    available: A::default(),
+                    held: A::default(),
+                    total: A::default(),
+                    locked: false,
+                    lock_reason: None,
+                };
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop what the ledger keeps about the client besides its account, its history and open
+    /// disputes with the side tables, and take its total out of the balance audit.
+    fn forget_client(&mut self, client: u16) {
+        let disputed_withdrawals = &mut self.disputed_withdrawals;
+        self.tx_history.retain(|tx, record| {
+            if record.client == client {
+                disputed_withdrawals.remove(tx);
+            }
+            record.client != client
+        });
+        self.client_logs.remove(&client);
+        self.open_disputes.remove(&client);
+        self.activity.remove(&client);
+        self.write_offs.remove(&client);
+        self.flagged.remove(&client);
+        if let (Some(expected), Some(account)) =
+            (&mut self.expected_total, self.accounts.get(&client))
+        {
+            *expected -= account.total;
+        }
+    }
+
+    /// Combine two ledgers holding disjoint clients, i.e built from shards of the same input.
+    pub fn merge(self, other: Ledger<A>) -> Result<Ledger<A>, MergeError> {
+        self.merge_with(other, MergePolicy::RejectOverlap)
+    }
+
+    /// Combine two ledgers, clients held by both are combined under `MergePolicy::CombineBalances`.
+    /// A tx id applied by both ledgers always fails the merge, its dispute state being ambiguous.
+    /// The config and metadata of `self` are kept.
+    pub fn merge_with(
+        mut self,
+        other: Ledger<A>,
+        policy: MergePolicy,
+    ) -> Result<Ledger<A>, MergeError> {
+        let overlapping: Vec<u16> = other
+            .accounts
+            .keys()
+            .filter(|client| self.accounts.contains_key(client))
+            .copied()
+            .collect();
+        if !overlapping.is_empty() && policy == MergePolicy::RejectOverlap {
+            return Err(MergeError::OverlappingClients(overlapping));
+        }
+        let conflicting: Vec<u32> = other
+            .tx_history
+            .keys()
+            .filter(|tx| self.tx_history.contains_key(tx))
+            .copied()
+            .collect();
+        if !conflicting.is_empty() {
+            return Err(MergeError::ConflictingTxs(conflicting));
+        }
+        for (client, account) in other.accounts {
+            match self.accounts.get_mut(&client) {
+                Some(existing) => existing.combine(&account),
+                None => {
+                    self.accounts.insert(client, account);
+                }
+            }
+        }
+        self.tx_history.extend(other.tx_history);
+        self.last_timestamp = self.last_timestamp.max(other.last_timestamp);
+        for (client, count) in other.client_tx_counts {
+            *self.client_tx_counts.entry(client).or_default() += count;
+        }
+        self.audit_trail.extend(other.audit_trail);
+        // the adjustments of other follow those of self in the merged id sequence.
+        let id_offset = self.adjustment_ids;
+        self.adjustments
+            .extend(other.adjustments.into_iter().map(|adjustment| Adjustment {
+                id: adjustment.id + id_offset,
+                ..adjustment
+            }));
+        self.adjustment_ids += other.adjustment_ids;
+        self.meta = self.meta.or(other.meta);
+        self.seen_keys.extend(other.seen_keys);
+        for (client, activity) in other.activity {
+            self.activity.entry(client).or_default().merge(&activity);
+        }
+        // the balance audit restarts from the merged system total on the next transaction.
+        self.expected_total = None;
+        self.imbalances.extend(other.imbalances);
+        self.flagged.extend(other.flagged);
+        for (client, log) in other.client_logs {
+            self.client_logs.entry(client).or_default().extend(log);
+        }
+        self.shortfall_disputes.extend(other.shortfall_disputes);
+        match (&mut self.amounts, other.amounts) {
+            (Some(amounts), Some(other)) => amounts.extend(other),
+            (None, other) => self.amounts = other,
+            (Some(_), None) => {}
+        }
+        // the finalized accounts not taken yet stay in client order.
+        self.finalized.extend(other.finalized);
+        self.finalized.sort_by_key(|account| account.client);
+        self.finalized_count += other.finalized_count;
+        for (client, written_off) in other.write_offs {
+            *self.write_offs.entry(client).or_default() += written_off;
+        }
+        self.stats.merge(&other.stats);
+        self.rejections.extend(other.rejections);
+        self.deferred.extend(other.deferred);
+        let offset = self.inputs.len();
+        self.inputs.extend(other.inputs);
+        for (tx, (input, line)) in other.tx_origins {
+            self.tx_origins.entry(tx).or_insert((input + offset, line));
+        }
+        self.duplicate_txs.extend(other.duplicate_txs);
+        self.disputed_withdrawals.extend(other.disputed_withdrawals);
+        for (client, open) in other.open_disputes {
+            *self.open_disputes.entry(client).or_default() += open;
+        }
+        self.seen_clients.extend(other.seen_clients);
+        self.transacted_clients.extend(other.transacted_clients);
+        Ok(self)
+    }
+
+    /// Lock the client account as an administrative freeze, returns whether the account existed.
+    pub fn freeze(&mut self, client: u16) -> bool {
+        match self.accounts.get_mut(&client) {
+            Some(account) => {
+                account.freeze();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove an account whose total is zero and that has no disputed transactions,
+    /// together with its history.
+    pub fn close(&mut self, client: u16) -> Result<(), CloseError> {
+        let account = self
+            .accounts
+            .get(&client)
+            .ok_or(CloseError::UnknownClient)?;
+        if account.total != A::default() {
+            return Err(CloseError::NonZeroBalance);
+        }
+        if self.has_open_dispute(client) {
+            return Err(CloseError::OpenDisputes);
+        }
+        self.forget_client(client);
+        self.accounts.remove(&client);
+        Ok(())
+    }
+
+    /// Transactions of the client currently held under dispute with their amount, sorted by tx id.
+    pub fn held_breakdown(&self, client: u16) -> Vec<(u32, A)> {
+        // the history keeps the latest state of each tx, a disputed one is stored as its dispute.
+        self.tx_history
+            .values()
+            .filter(|record| record.client == client && record.r#type == TxType::Dispute)
+            .map(|record| (record.tx, record.amount.unwrap_or_default()))
+            .collect()
+    }
+
+    /// Add `available * rate` interest to every unlocked account with positive available funds.
+    /// Accounts whose balances would overflow are skipped, returns how many accounts accrued.
+    /// The accrued interest is expected by the balance audit.
+    pub fn accrue_interest(&mut self, rate: f32) -> usize {
+        let mut accrued = 0;
+        let mut interest_total = A::default();
+        for account in self.accounts.values_mut() {
+            if account.locked || account.available <= A::default() {
+                continue;
+            }
+            let interest = account.available.checked_scale(rate);
+            let balances = interest.and_then(|interest| {
+                Some((
+                    account.available.checked_add(interest)?,
+                    account.total.checked_add(interest)?,
+                ))
+            });
+            match balances {
+                Some((available, total)) => {
+                    interest_total += total - account.total;
+                    account.available = available;
+                    account.total = total;
+                    accrued += 1;
+                }
+                None => eprintln!(
+                    "skipped interest for client {} : amount would overflow",
+                    account.client
+                ),
+            }
+        }
+        if let Some(expected) = &mut self.expected_total {
+            *expected += interest_total;
+        }
+        accrued
+    }
+
+    /// Amount held under open disputes per client, clients without any are left out.
+    pub fn disputed_totals(&self) -> BTreeMap<u16, A> {
+        let mut totals = BTreeMap::new();
+        for record in self.tx_history.values() {
+            if record.r#type == TxType::Dispute {
+                *totals.entry(record.client).or_default() += record.amount.unwrap_or_default();
+            }
+        }
+        totals
+    }
+
+    /// Clients whose held funds differ from the sum of their open disputes, with the unexplained
+    /// part of `held`, sorted by client. Empty as long as disputes and settlements stayed in sync;
+    /// balances loaded with `Ledger::with_accounts` have no disputes to back their held funds.
+    pub fn orphaned_held(&self) -> Vec<(u16, A)> {
+        let disputed = self.disputed_totals();
+        let tolerance = A::from_f32(INVARIANT_TOLERANCE);
+        self.accounts
+            .values()
+            .filter_map(|account| {
+                let orphaned =
+                    account.held - disputed.get(&account.client).copied().unwrap_or_default();
+                let zero = A::default();
+                let distance = if orphaned > zero {
+                    orphaned
+                } else {
+                    zero - orphaned
+                };
+                (distance > tolerance).then_some((account.client, orphaned))
+            })
+            .collect()
+    }
+
+    /// Clients seen in the offered transactions without any successful one, sorted by client,
+    /// for data-quality monitoring. Either every row of the client was rejected, or only a
+    /// non deposit opened it an account, which moves no funds, i.e a withdrawal-only client.
+    pub fn clients_without_accounts(&self) -> Vec<u16> {
+        self.seen_clients
+            .difference(&self.transacted_clients)
+            .copied()
+            .collect()
+    }
+
+    /// Locked clients still holding open disputes, i.e left in limbo by a chargeback of
+    /// another transaction, sorted by client.
+    pub fn locked_with_open_disputes(&self) -> Vec<u16> {
+        self.accounts
+            .values()
+            .filter(|account| account.locked && self.has_open_dispute(account.client))
+            .map(|account| account.client)
+            .collect()
+    }
+
+    /// Deposit `amount` into the client account.
+    pub fn deposit(&mut self, client: u16, tx: u32, amount: A) -> bool {
+        self.apply(Transaction::new(TxType::Deposit, client, tx, Some(amount)))
+    }
+
+    /// Withdraw `amount` from the client account.
+    pub fn withdrawal(&mut self, client: u16, tx: u32, amount: A) -> bool {
+        self.apply(Transaction::new(
+            TxType::Withdrawal,
+            client,
+            tx,
+            Some(amount),
+        ))
+    }
+
+    /// Dispute a previous client transaction.
+    pub fn dispute(&mut self, client: u16, tx: u32) -> bool {
+        self.apply(Transaction::new(TxType::Dispute, client, tx, None))
+    }
+
+    /// Resolve a disputed client transaction.
+    pub fn resolve(&mut self, client: u16, tx: u32) -> bool {
+        self.apply(Transaction::new(TxType::Resolve, client, tx, None))
+    }
+
+    /// Charge back a disputed client transaction.
+    pub fn chargeback(&mut self, client: u16, tx: u32) -> bool {
+        self.apply(Transaction::new(TxType::Chargeback, client, tx, None))
+    }
+}
+
+impl<A> Index<&u16> for Ledger<A> {
+    type Output = Account<A>;
+
+    fn index(&self, client: &u16) -> &Account<A> {
+        &self.accounts[client]
+    }
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::account::LockReason;
+    use crate::input::process_records;
+    use crate::output::write_audit_trail;
+
+    #[test]
+    fn test_dispute() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/dispute.csv", &Config::default()).unwrap();
+        assert_eq!(ledger[&1].available, -1.0);
+        assert_eq!(ledger[&1].held, 11.5);
+        assert_eq!(ledger[&1].total, 10.5);
+        assert!(!ledger[&1].locked);
+        Ok(())
+    }
+
+    #[test]
+    fn test_chargeback() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/chargeback.csv", &Config::default()).unwrap();
+        assert_eq!(ledger[&2].available, -3.0);
+        assert_eq!(ledger[&2].held, 0.0);
+        assert_eq!(ledger[&2].total, -3.0);
+        assert!(ledger[&2].locked);
+        Ok(())
+    }
+    #[test]
+    fn test_resolved() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/resolve.csv", &Config::default()).unwrap();
+        assert_eq!(ledger[&1].available, 0.5);
+        assert_eq!(ledger[&1].held, 0.0);
+        assert_eq!(ledger[&1].total, 0.5);
+        assert!(!ledger[&1].locked);
+        Ok(())
+    }
+    #[test]
+    fn test_withdrawal() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/withdrawal.csv", &Config::default()).unwrap();
+        assert_eq!(ledger[&1].available, 10.0);
+        assert_eq!(ledger[&1].held, 0.0);
+        assert!(!ledger[&1].locked);
+        Ok(())
+    }
+    #[test]
+    fn test_mixed() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/mixed.csv", &Config::default()).unwrap();
+        let expect_results = [
+            Account {
+                client: 1,
+                available: 199.0,
+                held: 0.0,
+                total: 199.0,
+                locked: true,
+                lock_reason: None,
+            },
+            Account {
+                client: 2,
+                available: 102.0,
+                held: 0.0,
+                total: 102.0,
+                locked: false,
+                lock_reason: None,
+            },
+            Account {
+                client: 3,
+                available: 200.0,
+                held: 100.0,
+                total: 300.0,
+                locked: false,
+                lock_reason: None,
+            },
+            Account {
+                client: 4,
+                available: 221.0,
+                held: 0.0,
+                total: 221.0,
+                locked: false,
+                lock_reason: None,
+            },
+            Account {
+                client: 5,
+                available: 241.0,
+                total: 241.0,
+                held: 0.0,
+                locked: false,
+                lock_reason: None,
+            },
+            Account {
+                client: 6,
+                available: 342.0,
+                total: 342.0,
+                held: 0.0,
+                locked: false,
+                lock_reason: None,
+            },
+            Account {
+                client: 7,
+                available: 134.0,
+                total: 134.0,
+                held: 0.0,
+                locked: false,
+                lock_reason: None,
+            },
+        ];
+        expect_results.iter().for_each(|ac| {
+            assert_eq!(ledger[&ac.client].available, ac.available);
+            assert_eq!(ledger[&ac.client].held, ac.held);
+            assert_eq!(ledger[&ac.client].total, ac.total);
+            assert_eq!(ledger[&ac.client].locked, ac.locked);
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_accounts() -> Result<(), Box<dyn Error>> {
+        let config = Config {
+            max_accounts: Some(2),
+            ..Config::default()
+        };
+        let ledger = process_records("src/tests/input/max_accounts.csv", &config)?;
+        assert_eq!(ledger.accounts().len(), 2);
+        assert_eq!(ledger[&1].total, 15.0);
+        assert_eq!(ledger[&2].total, 2.0);
+        assert!(!ledger.accounts().contains_key(&3));
+        assert!(!ledger.accounts().contains_key(&4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ordered_timestamps() -> Result<(), Box<dyn Error>> {
+        let config = Config {
+            require_ordered: true,
+            ..Config::default()
+        };
+        let ledger = process_records("src/tests/input/ordered.csv", &config)?;
+        assert_eq!(ledger[&1].available, 7.0);
+        assert_eq!(ledger[&2].total, 5.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_out_of_order_timestamp() -> Result<(), Box<dyn Error>> {
+        let config = Config {
+            require_ordered: true,
+            ..Config::default()
+        };
+        let ledger = process_records("src/tests/input/out_of_order.csv", &config)?;
+        // the withdrawal stamped before the previous row is dropped.
+        assert_eq!(ledger[&1].available, 12.0);
+        assert_eq!(ledger[&1].total, 12.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_client_txs() -> Result<(), Box<dyn Error>> {
+        let config = Config {
+            max_client_txs: Some(2),
+            ..Config::default()
+        };
+        let ledger = process_records("src/tests/input/client_txs.csv", &config)?;
+        // client 1 third and fourth transactions are dropped.
+        assert_eq!(ledger[&1].available, 3.0);
+        assert_eq!(ledger[&1].held, 0.0);
+        assert_eq!(ledger[&2].available, 3.0);
+
+        // rejected transactions do not use up the limit.
+        let mut ledger = Ledger::new(config);
+        ledger.deposit(1, 1, 1.0);
+        assert!(!ledger.withdrawal(1, 2, 5.0));
+        assert!(ledger.deposit(1, 3, 1.0));
+        assert!(!ledger.deposit(1, 4, 1.0));
+        assert_eq!(ledger[&1].total, 2.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_memo_audit_trail() -> Result<(), Box<dyn Error>> {
+        let config = Config {
+            record_audit_trail: true,
+            ..Config::default()
+        };
+        let ledger = process_records("src/tests/input/memo.csv", &config)?;
+        assert_eq!(ledger[&1].total, 8.0);
+        let memos: Vec<Option<&str>> = ledger
+            .audit_trail()
+            .iter()
+            .map(|record| record.memo.as_deref())
+            .collect();
+        assert_eq!(memos, vec![Some("salary"), None, Some("card lost")]);
+
+        let mut out = Vec::new();
+        write_audit_trail(&mut out, ledger.audit_trail())?;
+        let trail = String::from_utf8(out)?;
+        assert_eq!(
+            trail.lines().next(),
+            Some("client,tx,amount,fee,type,timestamp,memo,idempotency_key")
+        );
+        assert_eq!(trail.lines().nth(3), Some("1,2,2.0,,dispute,,card lost,"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_on_locked_account() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/resolve_locked.csv", &Config::default())?;
+        // the chargeback of tx 1 locks the account, tx 2 resolve must not release funds.
+        assert_eq!(ledger[&1].available, 0.0);
+        assert_eq!(ledger[&1].held, 5.0);
+        assert_eq!(ledger[&1].total, 5.0);
+        assert!(ledger[&1].locked);
+        Ok(())
+    }
+
+    #[test]
+    fn test_convenience_methods() {
+        let mut ledger = Ledger::new(Config::default());
+        assert!(ledger.deposit(1, 1, 10.0));
+        assert!(ledger.deposit(1, 2, 5.0));
+        assert!(ledger.withdrawal(1, 3, 3.0));
+        assert!(!ledger.withdrawal(1, 4, 100.0));
+        assert_eq!(ledger[&1].available, 12.0);
+
+        assert!(ledger.dispute(1, 2));
+        assert_eq!(ledger[&1].available, 7.0);
+        assert_eq!(ledger[&1].held, 5.0);
+        assert!(ledger.resolve(1, 2));
+        assert_eq!(ledger[&1].available, 12.0);
+        assert_eq!(ledger[&1].held, 0.0);
+
+        assert!(ledger.dispute(1, 1));
+        assert!(ledger.chargeback(1, 1));
+        assert_eq!(ledger[&1].available, 2.0);
+        assert_eq!(ledger[&1].total, 2.0);
+        assert!(ledger[&1].locked);
+        assert!(!ledger.deposit(1, 5, 1.0));
+    }
+
+    #[test]
+    fn test_held_overflow_rejected() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 3e38);
+        ledger.withdrawal(1, 2, 3e38);
+        assert!(ledger.dispute(1, 1));
+        assert_eq!(ledger[&1].held, 3e38);
+        // holding the withdrawal as well would push held past f32::MAX.
+        assert!(!ledger.dispute(1, 2));
+        assert_eq!(ledger[&1].held, 3e38);
+        assert_eq!(ledger[&1].available, -3e38);
+        assert!(ledger[&1].held.is_finite());
+    }
+
+    #[test]
+    fn test_reset_account() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 5.0);
+        ledger.dispute(1, 1);
+        ledger.chargeback(1, 1);
+        assert!(ledger[&1].locked);
+
+        assert!(ledger.reset(1));
+        assert!(ledger.accounts().contains_key(&1));
+        assert_eq!(ledger[&1].available, 0.0);
+        assert_eq!(ledger[&1].held, 0.0);
+        assert_eq!(ledger[&1].total, 0.0);
+        assert!(!ledger[&1].locked);
+        assert!(!ledger.reset(2));
+    }
+
+    #[test]
+    fn test_reset_drops_disputes() {
+        let mut ledger = Ledger::new(Config {
+            audit_balance: true,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 5.0);
+        ledger.dispute(1, 1);
+        assert!(ledger.reset(1));
+        assert!(ledger.transaction(1).is_none());
+        assert!(ledger.disputed_totals().is_empty());
+        // the dispute went with the reset, it can no longer drive held negative or lock.
+        assert!(!ledger.resolve(1, 1));
+        assert!(!ledger.chargeback(1, 1));
+        assert_eq!(ledger[&1].held, 0.0);
+        assert!(!ledger[&1].locked);
+        assert!(ledger.deposit(1, 3, 2.0));
+        assert_eq!(ledger[&1].total, 2.0);
+        assert!(ledger.imbalances().is_empty());
+    }
+
+    #[test]
+    fn test_close_account() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.withdrawal(1, 2, 10.0);
+        assert_eq!(ledger.close(1), Ok(()));
+        assert!(!ledger.accounts().contains_key(&1));
+        assert_eq!(ledger.close(1), Err(CloseError::UnknownClient));
+        assert!(ledger.transaction(1).is_none());
+        assert!(ledger.client_transactions(1).is_empty());
+        assert!(ledger.activity(1).is_none());
+    }
+
+    #[test]
+    fn test_close_rejected() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        assert_eq!(ledger.close(1), Err(CloseError::NonZeroBalance));
+
+        // a withdrawal dispute keeps the total at zero but holds funds.
+        ledger.withdrawal(1, 2, 10.0);
+        ledger.dispute(1, 2);
+        assert_eq!(ledger.close(1), Err(CloseError::OpenDisputes));
+        assert!(ledger.accounts().contains_key(&1));
+    }
+
+    #[test]
+    fn test_apply_all() {
+        let mut ledger = Ledger::new(Config::default());
+        let records = vec![
+            Transaction::new(TxType::Deposit, 1, 1, Some(10.0)),
+            Transaction::new(TxType::Withdrawal, 1, 2, Some(20.0)),
+            Transaction::new(TxType::Dispute, 1, 1, None),
+        ];
+        assert_eq!(ledger.apply_all(records), 2);
+        assert_eq!(ledger[&1].held, 10.0);
+    }
+
+    fn locked_ledger(lock_policy: LockPolicy) -> Ledger {
+        let mut ledger = Ledger::new(Config {
+            lock_policy,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 10.0);
+        ledger.withdrawal(1, 2, 4.0);
+        ledger.dispute(1, 1);
+        ledger.chargeback(1, 1);
+        ledger
+    }
+
+    #[test]
+    fn test_clients_without_accounts() -> Result<(), Box<dyn Error>> {
+        let mut ledger = Ledger::new(Config {
+            max_tx_amount: Some(1000.0),
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 10.0);
+        ledger.withdrawal(2, 2, 5.0);
+        ledger.dispute(3, 1);
+        ledger.deposit(4, 4, 5000.0);
+        assert_eq!(ledger.clients_without_accounts(), vec![2, 3, 4]);
+        assert_eq!(ledger[&2].total, 0.0);
+
+        ledger.deposit(2, 5, 1.0);
+        assert_eq!(ledger.clients_without_accounts(), vec![3, 4]);
+
+        let mixed = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        assert!(mixed.clients_without_accounts().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_adjust() {
+        let mut ledger = Ledger::new(Config {
+            record_audit_trail: true,
+            record_rejections: true,
+            audit_balance: true,
+            assert_invariants: true,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 10.0);
+        assert!(ledger.adjust(1, 2.5, "fee refund"));
+        assert!(ledger.adjust(1, -4.0, "duplicate payout correction"));
+        assert_eq!(ledger[&1].available, 8.5);
+        assert_eq!(ledger[&1].total, 8.5);
+        ledger.withdrawal(1, 2, 1.0);
+        assert!(ledger.imbalances().is_empty());
+        assert!(!ledger.adjust(2, 1.0, "unknown client"));
+
+        let notes: Vec<_> = ledger
+            .client_adjustments(1)
+            .into_iter()
+            .map(|adjustment| (adjustment.id, adjustment.delta, adjustment.note.as_str()))
+            .collect();
+        assert_eq!(
+            notes,
+            vec![
+                (1, 2.5, "fee refund"),
+                (2, -4.0, "duplicate payout correction")
+            ]
+        );
+        // adjustments stay out of the transaction logs.
+        assert_eq!(ledger.client_transactions(1).len(), 2);
+        assert_eq!(ledger.audit_trail().len(), 2);
+        // the failed adjustment is rejected like a transaction.
+        let rejection = &ledger.rejections()[0];
+        assert_eq!(
+            (rejection.tx, &rejection.r#type, rejection.reason),
+            (3, &Rejected::Adjustment, "unknown client")
+        );
+        let stats = ledger.stats();
+        assert_eq!((stats.processed, stats.applied, stats.rejected), (5, 4, 1));
+    }
+
+    #[test]
+    fn test_adjust_kept_apart_from_tx_ids() {
+        // the sorted input moving past a client keeps its adjustments.
+        let mut ledger = Ledger::new(Config {
+            sorted_by_client: true,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 10.0);
+        assert!(ledger.adjust(1, 1.0, "fee refund"));
+        ledger.deposit(2, 1, 5.0);
+        assert_eq!(ledger.client_adjustments(1)[0].note, "fee refund");
+
+        // a disputed tx with the id of an adjustment does not freeze the adjusted client.
+        let mut ledger = Ledger::new(Config {
+            freeze_deposits_on_dispute: true,
+            ..Config::default()
+        });
+        ledger.deposit(1, 2, 10.0);
+        assert!(ledger.adjust(1, 1.0, "fee refund"));
+        ledger.deposit(2, 1, 5.0);
+        assert!(ledger.dispute(2, 1));
+        assert!(ledger.deposit(1, 3, 1.0));
+        assert_eq!(ledger[&1].total, 12.0);
+        assert!(!ledger.dispute(1, 1));
+
+        // merged adjustments keep distinct ids.
+        let mut other = Ledger::new(Config::default());
+        other.deposit(3, 4, 1.0);
+        assert!(other.adjust(3, 1.0, "goodwill credit"));
+        let merged = ledger.merge(other).unwrap();
+        let ids: Vec<u32> = merged.adjustments().iter().map(|a| a.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_adjust_locked_account() {
+        let mut ledger = locked_ledger(LockPolicy::RejectDeposits);
+        assert!(!ledger.adjust(1, 1.0, "goodwill credit"));
+        assert_eq!(ledger[&1].total, -4.0);
+
+        let mut ledger = locked_ledger(LockPolicy::AllowDeposits);
+        assert!(ledger.adjust(1, 4.0, "debt settled"));
+        assert!(!ledger.adjust(1, -1.0, "late fee"));
+        assert_eq!(ledger[&1].total, 0.0);
+        assert_eq!(ledger[&1].available, 0.0);
+    }
+
+    #[test]
+    fn test_locked_deposit_rejected() {
+        let mut ledger = locked_ledger(LockPolicy::RejectDeposits);
+        assert!(ledger[&1].locked);
+        assert!(!ledger.deposit(1, 3, 5.0));
+        assert_eq!(ledger[&1].available, -4.0);
+        assert_eq!(ledger[&1].total, -4.0);
+    }
+
+    #[test]
+    fn test_locked_deposit_allowed() {
+        let mut ledger = locked_ledger(LockPolicy::AllowDeposits);
+        assert!(ledger[&1].locked);
+        assert!(ledger.deposit(1, 3, 5.0));
+        assert_eq!(ledger[&1].available, 1.0);
+        assert_eq!(ledger[&1].total, 1.0);
+        assert!(ledger[&1].locked);
+        // settling the debt does not unfreeze withdrawals.
+        assert!(!ledger.withdrawal(1, 4, 1.0));
+    }
+
+    #[test]
+    fn test_idempotency_key() {
+        let mut ledger = Ledger::new(Config::default());
+        let keyed = Transaction {
+            idempotency_key: Some("msg-1".to_string()),
+            ..Transaction::new(TxType::Deposit, 1, 1, Some(10.0))
+        };
+        assert!(ledger.apply(keyed.clone()));
+        assert!(!ledger.apply(keyed));
+        assert_eq!(ledger[&1].total, 10.0);
+
+        // transactions without a key are never deduplicated.
+        assert!(ledger.deposit(1, 2, 5.0));
+        assert_eq!(ledger[&1].total, 15.0);
+    }
+
+    #[test]
+    fn test_held_breakdown() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 2.5);
+        ledger.deposit(1, 3, 4.0);
+        ledger.deposit(2, 4, 1.0);
+        ledger.dispute(1, 2);
+        ledger.dispute(1, 1);
+        ledger.dispute(2, 4);
+        assert_eq!(ledger.held_breakdown(1), vec![(1, 10.0), (2, 2.5)]);
+        assert_eq!(ledger[&1].held, 12.5);
+
+        ledger.resolve(1, 1);
+        assert_eq!(ledger.held_breakdown(1), vec![(2, 2.5)]);
+        assert!(ledger.held_breakdown(3).is_empty());
+    }
+
+    #[test]
+    fn test_withdrawal_tolerance() {
+        // 0.1 + 3.3 sums to 3.3999999 in f32, just short of 3.4.
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 0.1);
+        ledger.deposit(1, 2, 3.3);
+        assert!(!ledger.withdrawal(1, 3, 3.4));
+
+        let mut ledger = Ledger::new(Config {
+            withdrawal_tolerance: 0.00005,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 0.1);
+        ledger.deposit(1, 2, 3.3);
+        assert!(ledger.withdrawal(1, 3, 3.4));
+        assert!(ledger[&1].available.abs() < 0.00005);
+        assert!(!ledger.withdrawal(1, 4, 0.001));
+    }
+
+    #[test]
+    fn test_chargeback_tolerance() {
+        // 0.5 - 0.3 leaves 0.19999999 held in f32, just short of 0.2.
+        let disputed = |config| {
+            let mut ledger = Ledger::new(config);
+            ledger.deposit(1, 1, 0.3);
+            ledger.deposit(1, 2, 0.2);
+            ledger.dispute(1, 1);
+            ledger.dispute(1, 2);
+            assert!(ledger.resolve(1, 1));
+            assert!(ledger[&1].held < 0.2);
+            ledger
+        };
+        assert!(!disputed(Config::default()).chargeback(1, 2));
+        // the withdrawal tolerance does not apply to chargebacks.
+        let withdrawal_tolerance = Config {
+            withdrawal_tolerance: 0.00005,
+            ..Config::default()
+        };
+        assert!(!disputed(withdrawal_tolerance).chargeback(1, 2));
+
+        let mut ledger = disputed(Config {
+            chargeback_tolerance: 0.00005,
+            ..Config::default()
+        });
+        assert!(ledger.chargeback(1, 2));
+        assert!(ledger[&1].held.abs() < 0.00005);
+        assert!((ledger[&1].total - 0.3).abs() < 0.00005);
+        assert!(ledger[&1].locked);
+    }
+
+    #[test]
+    fn test_client_activity() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        let activity = ledger.activity(1).expect("client 1 has activity");
+        assert_eq!(activity.deposited, 300.0);
+        assert_eq!(activity.withdrawn, 1.0);
+        assert_eq!(activity.disputes, 1);
+        assert_eq!(activity.chargebacks, 1);
+        assert_eq!(activity.net(), 299.0);
+        assert!(ledger.activity(99).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_simulate_chargeback() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 5.0);
+        ledger.withdrawal(1, 3, 3.0);
+        ledger.dispute(1, 2);
+        let before = ledger[&1].clone();
+
+        let charged = ledger.simulate_chargeback(2).expect("disputed deposit");
+        assert_eq!(charged.available, 7.0);
+        assert_eq!(charged.held, 0.0);
+        assert_eq!(charged.total, 7.0);
+        assert!(charged.locked);
+        // an undisputed deposit is disputed before being charged back.
+        let simulated = ledger.simulate_chargeback(1).expect("undisputed deposit");
+        assert_eq!(simulated.available, -3.0);
+        assert_eq!(simulated.held, 5.0);
+        assert_eq!(simulated.total, 2.0);
+        assert!(ledger.simulate_chargeback(99).is_none());
+
+        // the ledger is unchanged, the chargeback can still be applied for real.
+        assert_eq!(ledger[&1], before);
+        assert_eq!(
+            ledger.transaction(2).map(|tx| &tx.r#type),
+            Some(&TxType::Dispute)
+        );
+        assert!(ledger.chargeback(1, 2));
+        assert_eq!(ledger[&1], charged);
+        assert!(ledger.simulate_chargeback(2).is_none());
+    }
+
+    #[test]
+    fn test_invariants_hold() {
+        let mut ledger = Ledger::new(Config {
+            assert_invariants: true,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 5.5);
+        ledger.withdrawal(1, 3, 2.25);
+        ledger.dispute(1, 2);
+        ledger.resolve(1, 2);
+        ledger.dispute(1, 1);
+        ledger.chargeback(1, 1);
+        ledger.deposit(2, 4, 1.0);
+        assert_eq!(ledger[&1].total, 3.25);
+        assert!(ledger[&1].locked);
+    }
+
+    #[test]
+    fn test_withdrawal_fee() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/fee.csv", &Config::default())?;
+        // client 1 covers the amount but not the fee, client 2 covers both.
+        assert_eq!(ledger[&1].available, 10.0);
+        assert_eq!(ledger[&1].total, 10.0);
+        assert_eq!(ledger[&2].available, 3.5);
+        assert_eq!(ledger[&2].total, 3.5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_deposit() {
+        let mut ledger = Ledger::new(Config {
+            min_deposit: Some(1.0),
+            ..Config::default()
+        });
+        assert!(!ledger.deposit(1, 1, 0.0001));
+        assert!(!ledger.accounts().contains_key(&1));
+        assert!(ledger.deposit(1, 2, 1.0));
+        assert!(!ledger.deposit(1, 3, 0.5));
+        assert_eq!(ledger[&1].total, 1.0);
+    }
+
+    #[test]
+    fn test_audit_balance() -> Result<(), Box<dyn Error>> {
+        let config = Config {
+            audit_balance: true,
+            ..Config::default()
+        };
+        let ledger = process_records("src/tests/input/mixed.csv", &config)?;
+        assert!(ledger.imbalances().is_empty());
+
+        let mut ledger = Ledger::new(config);
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(2, 2, 5.0);
+        // an account moving by other than the expected delta is flagged once.
+        let record = Transaction::new(TxType::Withdrawal, 1, 3, Some(4.0));
+        ledger.audit_balance(&record, -1.0, -4.0);
+        ledger.dispute(1, 1);
+        assert_eq!(ledger.imbalances(), &[3]);
+        assert_eq!(ledger.expected_total, Some(14.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_available() {
+        let mut ledger = Ledger::new(Config {
+            min_available: Some(-5.0),
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 20.0);
+        ledger.withdrawal(1, 3, 22.0);
+        // holding tx 1 leaves -2.0 available, within the floor.
+        assert!(ledger.dispute(1, 1));
+        assert_eq!(ledger[&1].available, -2.0);
+        assert!(ledger.flagged().is_empty());
+
+        // holding tx 2 as well would leave -22.0.
+        assert!(!ledger.dispute(1, 2));
+        assert_eq!(ledger[&1].available, -2.0);
+        assert_eq!(ledger[&1].held, 10.0);
+        assert!(ledger.flagged().contains(&1));
+    }
+
+    #[test]
+    fn test_forward_reference_rejected() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 5.0);
+        // disputes only match transactions processed before them.
+        assert!(!ledger.dispute(1, 2));
+        assert!(ledger.deposit(1, 2, 10.0));
+        assert_eq!(ledger[&1].held, 0.0);
+
+        assert!(!ledger.dispute(2, 3));
+        assert!(ledger.deposit(2, 3, 4.0));
+        assert_eq!(ledger[&2].available, 4.0);
+        assert_eq!(ledger[&2].held, 0.0);
+        assert!(ledger.held_breakdown(2).is_empty());
+    }
+
+    #[test]
+    fn test_client_transactions() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(2, 2, 3.0);
+        ledger.withdrawal(1, 3, 4.0);
+        ledger.withdrawal(1, 4, 100.0);
+        ledger.dispute(1, 1);
+        ledger.resolve(1, 1);
+        let log: Vec<(TxType, u32)> = ledger
+            .client_transactions(1)
+            .iter()
+            .map(|record| (record.r#type.clone(), record.tx))
+            .collect();
+        assert_eq!(
+            log,
+            vec![
+                (TxType::Deposit, 1),
+                (TxType::Withdrawal, 3),
+                (TxType::Dispute, 1),
+                (TxType::Resolve, 1),
+            ]
+        );
+        assert!(ledger.client_transactions(3).is_empty());
+    }
+
+    #[test]
+    fn test_strict_control_amount() {
+        let amounted_dispute = || Transaction::new(TxType::Dispute, 1, 1, Some(3.0));
+        let mut ledger = Ledger::new(Config {
+            strict: true,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 10.0);
+        assert!(!ledger.apply(amounted_dispute()));
+        assert_eq!(ledger[&1].held, 0.0);
+        assert!(ledger.dispute(1, 1));
+
+        // outside strict mode the amount is ignored and the deposit amount held.
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        assert!(ledger.apply(amounted_dispute()));
+        assert_eq!(ledger[&1].held, 10.0);
+    }
+
+    #[test]
+    fn test_accrue_interest() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 100.0);
+        ledger.deposit(2, 2, 50.0);
+        ledger.dispute(2, 2);
+        ledger.chargeback(2, 2);
+        ledger.deposit(3, 3, 0.0);
+        ledger.deposit(4, 4, 3e38);
+        ledger.deposit(5, 5, 20.0);
+        ledger.dispute(5, 5);
+
+        assert_eq!(ledger.accrue_interest(0.5), 1);
+        assert_eq!(ledger[&1].available, 150.0);
+        assert_eq!(ledger[&1].total, 150.0);
+        // locked, zero and overflowing accounts are left as is.
+        assert_eq!(ledger[&2].total, 0.0);
+        assert_eq!(ledger[&3].total, 0.0);
+        assert_eq!(ledger[&4].total, 3e38);
+        // held funds do not accrue.
+        assert_eq!(ledger[&5].total, 20.0);
+
+        let mut ledger = Ledger::new(Config {
+            audit_balance: true,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 100.0);
+        assert_eq!(ledger.accrue_interest(0.5), 1);
+        ledger.withdrawal(1, 2, 10.0);
+        assert_eq!(ledger[&1].total, 140.0);
+        assert!(ledger.imbalances().is_empty());
+    }
+
+    #[test]
+    fn test_shortfall_dispute() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 5.0);
+        ledger.dispute(1, 2);
+        assert!(ledger.shortfall_disputes().is_empty());
+        ledger.resolve(1, 2);
+
+        ledger.withdrawal(1, 3, 12.0);
+        assert!(ledger.dispute(1, 1));
+        assert_eq!(ledger[&1].available, -7.0);
+        assert_eq!(ledger.shortfall_disputes(), &[1]);
+    }
+
+    #[test]
+    fn test_amount_percentile() {
+        let mut ledger = Ledger::new(Config {
+            amount_samples: Some(100),
+            ..Config::default()
+        });
+        for tx in 1..=20 {
+            ledger.deposit(1, tx, tx as f32);
+        }
+        ledger.dispute(1, 20);
+        assert_eq!(ledger.amount_percentile(50.0), Some(10.0));
+        assert_eq!(ledger.amount_percentile(95.0), Some(19.0));
+        assert_eq!(Ledger::new(Config::default()).amount_percentile(50.0), None);
+    }
+
+    #[test]
+    fn test_dispute_capped_at_deposits() {
+        let mut ledger = Ledger::new(Config {
+            cap_disputes_at_deposits: true,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 10.0);
+        ledger.withdrawal(1, 2, 8.0);
+        ledger.deposit(1, 3, 2.0);
+        assert!(ledger.dispute(1, 1));
+        // holding the withdrawal too would hold 18.0 out of 12.0 deposited.
+        assert!(!ledger.dispute(1, 2));
+        assert_eq!(ledger[&1].held, 10.0);
+        assert!(ledger.dispute(1, 3));
+        assert_eq!(ledger[&1].held, 12.0);
+    }
+
+    #[test]
+    fn test_negative_total_policy() -> Result<(), Box<dyn Error>> {
+        let allow = process_records("src/tests/input/chargeback.csv", &Config::default())?;
+        assert_eq!(allow[&2].total, -3.0);
+        assert!(allow.write_offs().is_empty());
+
+        let config = Config {
+            negative_total_policy: NegativeTotalPolicy::FloorZeroWriteOff,
+            audit_balance: true,
+            ..Config::default()
+        };
+        let floored = process_records("src/tests/input/chargeback.csv", &config)?;
+        assert_eq!(floored[&2].total, 0.0);
+        assert_eq!(floored[&2].available, 0.0);
+        assert_eq!(floored[&2].held, 0.0);
+        assert!(floored[&2].locked);
+        assert_eq!(floored.write_offs()[&2], 3.0);
+        assert!(floored.imbalances().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_chargeback_requires_open_dispute() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.dispute(1, 1);
+        ledger.resolve(1, 1);
+        let before = ledger[&1].clone();
+        assert!(!ledger.chargeback(1, 1));
+        assert_eq!(ledger[&1], before);
+        assert!(!ledger[&1].locked);
+
+        // a dispute whose held funds were already released cannot be charged back either.
+        ledger.dispute(1, 1);
+        ledger.accounts.get_mut(&1).unwrap().held = 4.0;
+        assert!(!ledger.chargeback(1, 1));
+        assert_eq!(ledger[&1].held, 4.0);
+    }
+
+    #[test]
+    fn test_deposits_frozen_on_dispute() {
+        let mut ledger = Ledger::new(Config {
+            freeze_deposits_on_dispute: true,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(2, 2, 1.0);
+        ledger.dispute(1, 1);
+        assert!(!ledger.deposit(1, 3, 5.0));
+        assert!(ledger.deposit(2, 4, 5.0));
+        assert_eq!(ledger[&1].total, 10.0);
+
+        ledger.resolve(1, 1);
+        assert!(ledger.deposit(1, 5, 5.0));
+        assert_eq!(ledger[&1].available, 15.0);
+
+        // deposits stay frozen until every open dispute is settled.
+        ledger.deposit(1, 6, 1.0);
+        ledger.dispute(1, 5);
+        ledger.dispute(1, 6);
+        ledger.resolve(1, 5);
+        assert!(!ledger.deposit(1, 7, 1.0));
+        ledger.resolve(1, 6);
+        assert!(ledger.deposit(1, 8, 1.0));
+    }
+
+    #[test]
+    fn test_max_tx_amount() {
+        let mut ledger = Ledger::new(Config {
+            max_tx_amount: Some(1000.0),
+            ..Config::default()
+        });
+        assert!(!ledger.deposit(1, 1, 1e9));
+        assert!(!ledger.accounts().contains_key(&1));
+        assert!(ledger.deposit(1, 2, 1000.0));
+        assert!(!ledger.withdrawal(1, 3, 1000.5));
+        assert_eq!(ledger[&1].total, 1000.0);
+    }
+
+    #[test]
+    fn test_dispute_stats() -> Result<(), Box<dyn Error>> {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 5.0);
+        ledger.withdrawal(1, 3, 2.0);
+        ledger.deposit(2, 4, 8.0);
+        ledger.dispute(1, 1);
+        ledger.dispute(1, 3);
+        ledger.dispute(2, 4);
+        ledger.dispute(2, 9);
+        ledger.resolve(1, 3);
+        ledger.resolve(1, 2);
+        ledger.chargeback(1, 1);
+        ledger.chargeback(2, 4);
+        assert_eq!(
+            ledger.stats(),
+            &ProcessingStats {
+                processed: 12,
+                applied: 10,
+                rejected: 2,
+                disputed_deposits: 2,
+                disputed_withdrawals: 1,
+                resolved: 1,
+                charged_back: 2,
+            }
+        );
+
+        let mixed = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        let stats = mixed.stats();
+        assert_eq!(stats.applied + stats.rejected, stats.processed);
+        assert_eq!(stats.processed, mixed.processed());
+        Ok(())
+    }
+
+    #[test]
+    fn test_chargeback_requires_dispute_of_same_tx() {
+        let mut ledger = Ledger::new(Config {
+            record_rejections: true,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 5.0);
+        assert!(!ledger.chargeback(1, 1));
+        // a dispute of another tx does not open tx 1 to a chargeback.
+        ledger.dispute(1, 2);
+        assert!(!ledger.chargeback(1, 1));
+        // nor does a dispute already resolved.
+        ledger.resolve(1, 2);
+        assert!(!ledger.chargeback(1, 2));
+        assert!(!ledger[&1].locked);
+        assert_eq!(ledger[&1].total, 15.0);
+        let reasons: Vec<_> = ledger
+            .rejections()
+            .iter()
+            .map(|r| (r.tx, r.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (1, "transaction is not disputed"),
+                (1, "transaction is not disputed"),
+                (2, "transaction is not disputed"),
+            ]
+        );
+
+        ledger.dispute(1, 1);
+        assert!(ledger.chargeback(1, 1));
+        assert!(ledger[&1].locked);
+        assert_eq!(ledger[&1].held, 0.0);
+        assert_eq!(ledger[&1].total, 5.0);
+    }
+
+    #[test]
+    fn test_orphaned_held() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        assert!(ledger.orphaned_held().is_empty());
+
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 2.5);
+        ledger.dispute(1, 1);
+        ledger.dispute(1, 2);
+        ledger.resolve(1, 1);
+        ledger.deposit(3, 4, 7.0);
+        ledger.dispute(3, 4);
+        ledger.chargeback(3, 4);
+        assert!(ledger.orphaned_held().is_empty());
+
+        // a snapshot carries held funds without the disputes behind them.
+        let mut accounts = ledger.accounts().clone();
+        accounts.insert(
+            4,
+            Account {
+                client: 4,
+                available: 1.0,
+                held: 3.0,
+                total: 4.0,
+                locked: false,
+                lock_reason: None,
+            },
+        );
+        let restored = Ledger::with_accounts(accounts, Config::default());
+        assert_eq!(restored.orphaned_held(), vec![(1, 2.5), (4, 3.0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sorted_by_client() -> Result<(), Box<dyn Error>> {
+        let path = "src/tests/input/sorted_by_client.csv";
+        let general = process_records(path, &Config::default())?;
+        let config = Config {
+            sorted_by_client: true,
+            ..Config::default()
+        };
+        let sorted = process_records(path, &config)?;
+        assert_eq!(sorted.accounts(), general.accounts());
+        assert_eq!(sorted.disputed_totals(), general.disputed_totals());
+        assert!(sorted.orphaned_held().is_empty());
+        // only the open dispute of client 1 and the history of the last client are kept.
+        assert_eq!(general.history_len(), 8);
+        assert_eq!(sorted.history_len(), 2);
+        assert!(sorted.client_transactions(2).is_empty());
+
+        let mut sorted = Ledger::new(config);
+        for (client, tx) in [(1, 1), (2, 2), (3, 3)] {
+            sorted.deposit(client, tx, 1.0);
+            assert!(sorted.history_len() <= 1);
+        }
+        assert!(!sorted.deposit(2, 4, 1.0));
+        assert_eq!(sorted[&2].total, 1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_withdrawal_dispute_checks_account() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.withdrawal(1, 2, 4.0);
+        ledger.deposit(2, 3, 1.0);
+        // another client cannot dispute the withdrawal.
+        assert!(!ledger.dispute(2, 2));
+        assert_eq!(ledger[&2].held, 0.0);
+        assert_eq!(ledger[&1].held, 0.0);
+
+        ledger.freeze(1);
+        assert!(!ledger.dispute(1, 2));
+        assert_eq!(ledger[&1].available, 6.0);
+        assert_eq!(ledger[&1].held, 0.0);
+    }
+
+    #[test]
+    fn test_withdrawal_dispute_cycles() {
+        let disputed = |policy| {
+            let mut ledger = Ledger::new(Config {
+                withdrawal_resolve_policy: policy,
+                audit_balance: true,
+                ..Config::default()
+            });
+            ledger.deposit(1, 1, 10.0);
+            ledger.withdrawal(1, 2, 4.0);
+            assert!(ledger.dispute(1, 2));
+            assert_eq!(ledger[&1].available, 2.0);
+            assert_eq!(ledger[&1].held, 4.0);
+            assert_eq!(ledger[&1].total, 6.0);
+            ledger
+        };
+
+        // resolving returns the held funds, the balances are back to before the dispute.
+        let mut ledger = disputed(WithdrawalResolvePolicy::ReturnToAvailable);
+        assert!(ledger.resolve(1, 2));
+        assert_eq!(ledger[&1].available, 6.0);
+        assert_eq!(ledger[&1].held, 0.0);
+        assert_eq!(ledger[&1].total, 6.0);
+        assert!(ledger.imbalances().is_empty());
+
+        let mut ledger = disputed(WithdrawalResolvePolicy::SettledExternally);
+        assert!(ledger.resolve(1, 2));
+        assert_eq!(ledger[&1].available, 2.0);
+        assert_eq!(ledger[&1].held, 0.0);
+        assert_eq!(ledger[&1].total, 2.0);
+        assert!(ledger.imbalances().is_empty());
+        // the policy only applies to withdrawals.
+        ledger.deposit(1, 3, 5.0);
+        ledger.dispute(1, 3);
+        assert!(ledger.resolve(1, 3));
+        assert_eq!(ledger[&1].total, 7.0);
+        assert_eq!(ledger[&1].available, 7.0);
+
+        // a chargeback removes the held funds and locks, whatever the resolve policy.
+        for policy in [
+            WithdrawalResolvePolicy::ReturnToAvailable,
+            WithdrawalResolvePolicy::SettledExternally,
+        ] {
+            let mut ledger = disputed(policy);
+            assert!(ledger.chargeback(1, 2));
+            assert_eq!(ledger[&1].available, 2.0);
+            assert_eq!(ledger[&1].held, 0.0);
+            assert_eq!(ledger[&1].total, 2.0);
+            assert!(ledger[&1].locked);
+            assert!(!ledger.resolve(1, 2));
+            assert!(ledger.imbalances().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_charged_back_tx_id_not_reused() {
+        let mut ledger = Ledger::new(Config {
+            lock_policy: LockPolicy::AllowDeposits,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 10.0);
+        ledger.dispute(1, 1);
+        assert!(ledger.chargeback(1, 1));
+        assert!(!ledger.deposit(1, 1, 5.0));
+        assert!(!ledger.deposit(2, 1, 5.0));
+        assert_eq!(
+            ledger.transaction(1).map(|record| &record.r#type),
+            Some(&TxType::Chargeback)
+        );
+        assert!(!ledger.accounts().contains_key(&2));
+        assert_eq!(ledger[&1].total, 0.0);
+        // a fresh id is still accepted by the locked account.
+        assert!(ledger.deposit(1, 2, 5.0));
+        assert_eq!(ledger[&1].total, 5.0);
+    }
+
+    #[test]
+    fn test_lock_reason() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(2, 2, 5.0);
+        ledger.deposit(3, 3, 1.0);
+        ledger.dispute(1, 1);
+        ledger.chargeback(1, 1);
+        assert!(ledger.freeze(2));
+        assert!(!ledger.freeze(4));
+        assert_eq!(ledger[&1].lock_reason, Some(LockReason::Chargeback));
+        assert_eq!(ledger[&2].lock_reason, Some(LockReason::AdminFreeze));
+        assert!(ledger[&2].locked);
+        assert!(!ledger.withdrawal(2, 4, 1.0));
+        assert_eq!(ledger[&3].lock_reason, None);
+
+        // a frozen account charged back keeps its original reason.
+        ledger.dispute(3, 3);
+        ledger.freeze(3);
+        assert!(ledger.chargeback(3, 3));
+        assert_eq!(ledger[&3].lock_reason, Some(LockReason::AdminFreeze));
+        ledger.reset(1);
+        assert_eq!(ledger[&1].lock_reason, None);
+    }
+
+    #[test]
+    fn test_deferred_control_transactions() {
+        let reordered = |config: Config| {
+            let mut ledger = Ledger::new(config);
+            ledger.deposit(1, 1, 5.0);
+            ledger.deposit(2, 2, 1.0);
+            ledger.dispute(1, 3);
+            ledger.chargeback(1, 3);
+            ledger.deposit(1, 3, 10.0);
+            ledger.dispute(2, 4);
+            ledger.deposit(2, 5, 1.0);
+            ledger.deposit(2, 6, 1.0);
+            ledger.deposit(2, 4, 2.0);
+            ledger.flush_deferred();
+            ledger
+        };
+        let ledger = reordered(Config::default());
+        assert_eq!(ledger[&1].total, 15.0);
+        assert!(!ledger[&1].locked);
+
+        let ledger = reordered(Config {
+            defer_window: Some(2),
+            record_rejections: true,
+            ..Config::default()
+        });
+        // the dispute and chargeback of tx 3 are applied in order once it shows up.
+        assert_eq!(ledger[&1].available, 5.0);
+        assert_eq!(ledger[&1].held, 0.0);
+        assert_eq!(ledger[&1].total, 5.0);
+        assert!(ledger[&1].locked);
+        // tx 4 shows up 3 rows after its dispute, past the window.
+        assert_eq!(ledger[&2].held, 0.0);
+        assert_eq!(ledger[&2].total, 5.0);
+        let rejected: Vec<u32> = ledger.rejections().iter().map(|r| r.tx).collect();
+        assert_eq!(rejected, vec![4]);
+        assert_eq!(ledger.processed(), 9);
+
+        // the records running out flushes what is still deferred.
+        let mut ledger = Ledger::new(Config {
+            defer_window: Some(5),
+            record_rejections: true,
+            ..Config::default()
+        });
+        let records = [
+            Transaction::new(TxType::Deposit, 1, 1, Some(5.0)),
+            Transaction::new(TxType::Dispute, 1, 9, None),
+        ];
+        assert_eq!(ledger.apply_all(records), 1);
+        assert_eq!(ledger.processed(), 2);
+        let rejected: Vec<u32> = ledger.rejections().iter().map(|r| r.tx).collect();
+        assert_eq!(rejected, vec![9]);
+
+        // the client order is checked when a record arrives, not when it is retried.
+        let mut ledger = Ledger::new(Config {
+            defer_window: Some(1),
+            sorted_by_client: true,
+            record_rejections: true,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 5.0);
+        ledger.dispute(1, 9);
+        ledger.deposit(2, 2, 1.0);
+        ledger.dispute(1, 10);
+        let reasons: Vec<_> = ledger
+            .rejections()
+            .iter()
+            .map(|r| (r.tx, r.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (9, "referenced transaction not found"),
+                (10, "input not sorted by client"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_restores_balances() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 2.5);
+        ledger.withdrawal(1, 3, 1.25);
+        for tx in 1..=3 {
+            let before = ledger[&1].clone();
+            assert!(ledger.dispute(1, tx));
+            assert_ne!(ledger[&1], before);
+            assert!(ledger.resolve(1, tx));
+            assert_eq!(ledger[&1], before, "dispute cycle of tx {}", tx);
+        }
+
+        // resolving one dispute reverses only its own, the other stays held.
+        ledger.deposit(1, 4, 3.0);
+        ledger.deposit(1, 5, 0.5);
+        let before = ledger[&1].clone();
+        assert!(ledger.dispute(1, 4));
+        let one_open = ledger[&1].clone();
+        assert!(ledger.dispute(1, 5));
+        assert!(ledger.resolve(1, 5));
+        assert_eq!(ledger[&1], one_open);
+        assert_eq!(ledger[&1].held, 3.0);
+        assert!(ledger.resolve(1, 4));
+        assert_eq!(ledger[&1], before);
+        assert_eq!(ledger[&1].available, 14.75);
+    }
+
+    #[test]
+    fn test_locked_with_open_disputes() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 5.0);
+        ledger.deposit(2, 3, 4.0);
+        ledger.deposit(3, 4, 1.0);
+        ledger.dispute(1, 1);
+        ledger.dispute(1, 2);
+        ledger.chargeback(1, 1);
+        ledger.dispute(2, 3);
+        ledger.freeze(3);
+        assert!(ledger[&1].locked);
+        assert_eq!(ledger[&1].held, 5.0);
+        // client 2 dispute is open but its account is not locked, client 3 has no dispute.
+        assert_eq!(ledger.locked_with_open_disputes(), vec![1]);
+
+        ledger.chargeback(1, 2);
+        assert!(ledger.locked_with_open_disputes().is_empty());
+    }
+
+    #[test]
+    fn test_reject_non_finite() {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 3e38);
+        assert!(ledger.deposit(1, 2, 3e38));
+        assert!(ledger[&1].total.is_infinite());
+
+        let mut ledger = Ledger::new(Config {
+            reject_non_finite: true,
+            record_rejections: true,
+            ..Config::default()
+        });
+        ledger.deposit(1, 1, 3e38);
+        assert!(!ledger.deposit(1, 2, 3e38));
+        assert_eq!(ledger[&1].available, 3e38);
+        assert_eq!(ledger[&1].total, 3e38);
+        // an account opened with an infinite amount is not kept.
+        assert!(!ledger.deposit(2, 3, f32::INFINITY));
+        assert!(!ledger.accounts().contains_key(&2));
+        assert!(ledger.deposit(1, 4, 1.0));
+        assert_eq!(ledger.rejections()[0].reason, "balance would not be finite");
+        assert_eq!(ledger.rejections().len(), 2);
+    }
+
+    #[test]
+    fn test_merge_disjoint_shards() -> Result<(), Box<dyn Error>> {
+        let full = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        let shard = |worker| {
+            let config = Config {
+                shard: Some(Shard { workers: 2, worker }),
+                ..Config::default()
+            };
+            process_records("src/tests/input/mixed.csv", &config)
+        };
+        let merged = shard(0)?.merge(shard(1)?)?;
+        assert_eq!(merged.accounts(), full.accounts());
+        assert_eq!(merged.disputed_totals(), full.disputed_totals());
+        assert_eq!(merged.stats(), full.stats());
+        assert_eq!(merged.activity(1), full.activity(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_finalized_and_samples() {
+        let streamed = |clients: [u16; 2], samples| {
+            let mut ledger = Ledger::new(Config {
+                sorted_by_client: true,
+                stream_accounts: true,
+                max_accounts: Some(4),
+                amount_samples: samples,
+                ..Config::default()
+            });
+            for client in clients {
+                ledger.deposit(client, u32::from(client), f32::from(client));
+            }
+            ledger
+        };
+        // client 1 and 3 are finalized, 2 and 4 are still current.
+        let mut merged = streamed([1, 2], None)
+            .merge(streamed([3, 4], Some(10)))
+            .unwrap();
+        let finalized: Vec<u16> = merged
+            .take_finalized()
+            .iter()
+            .map(|account| account.client)
+            .collect();
+        assert_eq!(finalized, vec![1, 3]);
+        assert_eq!(merged.amount_percentile(100.0), Some(4.0));
+        // the finalized accounts still count against the limit.
+        assert!(!merged.deposit(5, 5, 1.0));
+    }
+
+    #[test]
+    fn test_merge_overlapping_clients() {
+        let ledger = |tx| {
+            let mut ledger = Ledger::new(Config::default());
+            ledger.deposit(1, tx, 10.0);
+            ledger.deposit(2, tx + 1, 5.0);
+            ledger.dispute(2, tx + 1);
+            ledger
+        };
+        let mut other = Ledger::new(Config::default());
+        other.deposit(1, 10, 1.0);
+        assert_eq!(
+            ledger(1).merge(other).unwrap_err(),
+            MergeError::OverlappingClients(vec![1])
+        );
+
+        let merged = ledger(1)
+            .merge_with(ledger(3), MergePolicy::CombineBalances)
+            .unwrap();
+        assert_eq!(merged[&1].total, 20.0);
+        assert_eq!(merged[&2].held, 10.0);
+        assert_eq!(merged.held_breakdown(2), vec![(2, 5.0), (4, 5.0)]);
+        // the held funds of both disputes can still be released.
+        let mut merged = merged;
+        assert!(merged.resolve(2, 4));
+        assert_eq!(merged[&2].available, 5.0);
+
+        let conflict = ledger(1).merge_with(ledger(2), MergePolicy::CombineBalances);
+        assert_eq!(conflict.unwrap_err(), MergeError::ConflictingTxs(vec![2]));
+    }
+}
@@ -0,0 +1,62 @@
+//! Toy ledger processing client transactions and reporting the final account balances.
+//!
+//! Reading and writing csv is behind the default `csv` feature, without it transactions
+//! are applied directly through `Ledger::apply` and `Ledger::apply_all`.
+//! The optional `sqlite` feature adds `write_sqlite` storing the final accounts in a database,
+//! the optional `async` feature adds `Ledger::process_stream` applying an async stream of transactions.
+//! Amounts are `f32` by default, `DynLedger` picks `f32`, `f64` or `FixedPoint` amounts at runtime.
+
+mod account;
+mod activity;
+mod amount;
+mod backend;
+mod error;
+mod fixed;
+mod generate;
+#[cfg(feature = "csv")]
+mod input;
+mod json;
+mod ledger;
+mod meta;
+mod output;
+mod percentile;
+mod snapshot;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stats;
+#[cfg(feature = "async")]
+mod stream;
+mod transaction;
+
+pub use account::{Account, LockPolicy, LockReason, NegativeTotalPolicy, WithdrawalResolvePolicy};
+pub use activity::ClientActivity;
+pub use amount::{
+    normalize_amount, parse_amount, parse_amount_with, parse_currency_amount, parse_grouped_amount,
+    strip_currency_symbol, Amount, DecimalSeparator,
+};
+pub use backend::{AmountBackend, DynLedger};
+pub use error::{CloseError, LedgerError, MergeError};
+pub use fixed::FixedPoint;
+#[cfg(feature = "csv")]
+pub use generate::write_transactions;
+pub use generate::{generate_transactions, GeneratorConfig};
+#[cfg(feature = "csv")]
+pub use input::{csv_files_in, open_input, process_records};
+pub use ledger::{Config, DuplicateTx, Ledger, MergePolicy, Rejected, Rejection, RowOrigin, Shard};
+pub use meta::LedgerMeta;
+pub use output::{
+    write_accounts, write_accounts_chunked, write_accounts_locked_as, write_disputed_accounts,
+    write_event_count_accounts, write_formatted_accounts, write_ledger, write_lock_reason_accounts,
+    write_minor_units_accounts, write_reconciled_accounts, write_run_metadata,
+    write_scaled_accounts, write_split, write_summary, write_verified_accounts, LockedStyle,
+    OutputFormat,
+};
+#[cfg(feature = "csv")]
+pub use output::{write_audit_trail, write_rejections};
+pub use snapshot::changed_accounts;
+#[cfg(feature = "csv")]
+pub use snapshot::load_snapshot;
+#[cfg(feature = "sqlite")]
+pub use sqlite::write_sqlite;
+pub use stats::ProcessingStats;
+pub use transaction::{Adjustment, Transaction, TxType};
@@ -1,283 +1,610 @@
-use std::{fmt::Display, process};
+use std::process;
 
-use std::collections::HashMap;
 use std::env;
-use std::error::Error;
-
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "lowercase")]
-#[derive(PartialEq)]
-enum TxType {
-    Deposit,
-    Withdrawal,
-    Dispute,
-    Resolve,
-    Chargeback,
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use mini_ledger::{
+    changed_accounts, load_snapshot, write_accounts, write_accounts_chunked,
+    write_accounts_locked_as, write_audit_trail, write_disputed_accounts,
+    write_event_count_accounts, write_formatted_accounts, write_lock_reason_accounts,
+    write_minor_units_accounts, write_reconciled_accounts, write_rejections, write_run_metadata,
+    write_scaled_accounts, write_summary, write_verified_accounts, AmountBackend, Config,
+    DecimalSeparator, DynLedger, Ledger, LedgerError, LockPolicy, LockedStyle, NegativeTotalPolicy,
+    OutputFormat, WithdrawalResolvePolicy,
+};
+
+/// Command line options, wrapping the processing limits applied while building the ledger.
+#[derive(Debug, Default)]
+struct Options {
+    config: Config,
+    /// Further input files applied after the first one.
+    files: Vec<String>,
+    /// Snapshot of a previous run to start from, only changed accounts are reported.
+    snapshot: Option<String>,
+    /// File the audit trail of applied transactions is written to.
+    audit_trail: Option<String>,
+    /// File the report of rejected transactions is written to.
+    warnings_out: Option<String>,
+    /// Layout of the account rows, the regular csv by default.
+    layout: Layout,
+    /// Flag that picked the layout, for the conflict messages.
+    layout_flag: Option<&'static str>,
+    /// Write each account as soon as the client-sorted input moves past it.
+    stream: bool,
+    /// Start the output with a comment line describing the run.
+    run_metadata: bool,
+    /// End the output with a comment line of the summed balances and locked accounts.
+    summary: bool,
+    /// Csv of disputed tx ids to resolve once the input is processed.
+    resolve: Option<String>,
+    /// Fail with `CliError::EmptyLedger` once the output is written if the ledger has no accounts.
+    error_on_empty: bool,
+    /// Amount representation, any other than `f32` only writes the regular csv output.
+    amount_backend: AmountBackend,
+    /// SQLite database the final accounts are also written to.
+    #[cfg(feature = "sqlite")]
+    sqlite: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-/// Represents incoming transaction from csv.
-struct Transaction {
-    client: u16,
-    tx: u32,
-    amount: Option<f32>,
-    r#type: TxType,
+/// How the account rows are written, picked by at most one layout flag.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Layout {
+    /// The regular `client, available, held, total, locked` csv.
+    #[default]
+    Plain,
+    /// Available recomputed as `total - held`, flagging mismatches.
+    Verify,
+    /// An extra `disputed` column with the amount held under open disputes.
+    Disputed,
+    /// Extra `deposits`, `withdrawals` and `disputes` count columns.
+    EventCounts,
+    /// An extra `lock_reason` column with what locked each account.
+    LockReason,
+    /// Amounts as integer ten-thousandths.
+    MinorUnits,
+    /// Amounts divided by the scale, i.e `1000` to report in thousands.
+    Scaled(f64),
+    /// Amounts rounded to this many decimals, keeping the column sums consistent.
+    Rounded(u32),
+    /// The regular csv, flushed every this many rows.
+    Chunked(usize),
+    /// The regular csv with the `locked` column rendered in the style.
+    LockedAs(LockedStyle),
+    /// Another output format.
+    Formatted(OutputFormat),
 }
 
-#[derive(Debug, Serialize)]
-struct Account {
-    client: u16,
-    available: f32,
-    held: f32,
-    total: f32,
-    locked: bool,
+impl Layout {
+    /// Whether `#` comment lines, i.e the run metadata or the summary, can be added to the output.
+    fn allows_comments(self) -> bool {
+        match self {
+            Layout::Formatted(format) => format.allows_comments(),
+            _ => true,
+        }
+    }
 }
 
-/// Writes out account data with 4 precision points.
-impl Display for Account {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{},{:.4},{:.4},{:.4},{}",
-            self.client, self.available, self.held, self.total, self.locked
-        )
+/// Pick the layout of `flag`, failing if another layout flag already picked one.
+/// The default layout, i.e `--format csv`, picks nothing.
+fn set_layout(options: &mut Options, flag: &'static str, layout: Layout) -> Result<(), String> {
+    let default = matches!(
+        layout,
+        Layout::Plain | Layout::LockedAs(LockedStyle::Bool) | Layout::Formatted(OutputFormat::Csv)
+    );
+    if default {
+        return Ok(());
     }
+    if let Some(previous) = options.layout_flag {
+        return Err(format!("{}, {} cannot be combined", previous, flag));
+    }
+    options.layout = layout;
+    options.layout_flag = Some(flag);
+    Ok(())
 }
-/// Verify matching client id and non locked account for every operation.
-impl Account {
-    /// Add deposit amount to an Account.
-    fn deposit(&mut self, record: &Transaction) -> bool {
-        if !self.locked && self.client == record.client {
-            self.available += record.amount.unwrap_or(0.0);
-            self.total += record.amount.unwrap_or(0.0);
-            return true;
+
+/// Most decimals `--round-decimals` accepts, keeping the rounded units within an `i64`.
+const MAX_ROUND_DECIMALS: u32 = 9;
+
+/// Parse the optional flags and extra input files following the input file path.
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let config = &mut options.config;
+        match arg.as_str() {
+            "--max-accounts" => config.max_accounts = Some(parse_value(arg, args.next())?),
+            "--max-client-txs" => config.max_client_txs = Some(parse_value(arg, args.next())?),
+            "--withdrawal-tolerance" => {
+                config.withdrawal_tolerance = parse_value(arg, args.next())?
+            }
+            "--chargeback-tolerance" => {
+                config.chargeback_tolerance = parse_value(arg, args.next())?
+            }
+            "--delimiter" => {
+                let delimiter: char = parse_value(arg, args.next())?;
+                let delimiter = u8::try_from(delimiter)
+                    .map_err(|_| format!("invalid {} value : {}", arg, delimiter))?;
+                config.delimiter = Some(delimiter);
+            }
+            "--decimal-comma" => config.decimal_separator = DecimalSeparator::Comma,
+            "--grouped-amounts" => config.grouped_amounts = true,
+            "--currency-symbol" => config.currency_symbol = Some(parse_value(arg, args.next())?),
+            "--case-insensitive" => config.case_insensitive = true,
+            "--column-alias" => {
+                let alias = args
+                    .next()
+                    .ok_or("--column-alias requires <alias>=<column>")?;
+                let (alias, column) = alias
+                    .split_once('=')
+                    .ok_or_else(|| format!("invalid {} value : {}", arg, alias))?;
+                config
+                    .column_aliases
+                    .insert(alias.trim().to_string(), column.trim().to_string());
+            }
+            "--max-record-size" => config.max_record_size = Some(parse_value(arg, args.next())?),
+            "--min-deposit" => config.min_deposit = Some(parse_value(arg, args.next())?),
+            "--max-tx-amount" => config.max_tx_amount = Some(parse_value(arg, args.next())?),
+            "--defer-window" => config.defer_window = Some(parse_value(arg, args.next())?),
+            "--shard" => config.shard = Some(parse_value(arg, args.next())?),
+            "--require-ordered" => config.require_ordered = true,
+            "--lenient" => config.lenient = true,
+            "--strict-schema" => config.strict_schema = true,
+            "--strict" => config.strict = true,
+            "--reject-non-finite" => config.reject_non_finite = true,
+            "--sorted-by-client" => config.sorted_by_client = true,
+            "--stream" => {
+                config.sorted_by_client = true;
+                config.stream_accounts = true;
+                options.stream = true;
+            }
+            "--skip-unreadable" => config.skip_unreadable_files = true,
+            "--freeze-deposits-on-dispute" => config.freeze_deposits_on_dispute = true,
+            "--allow-locked-deposits" => config.lock_policy = LockPolicy::AllowDeposits,
+            "--settle-withdrawal-disputes-externally" => {
+                config.withdrawal_resolve_policy = WithdrawalResolvePolicy::SettledExternally
+            }
+            "--floor-negative-totals" => {
+                config.negative_total_policy = NegativeTotalPolicy::FloorZeroWriteOff
+            }
+            "--verify" => set_layout(&mut options, "--verify", Layout::Verify)?,
+            "--minor-units" => set_layout(&mut options, "--minor-units", Layout::MinorUnits)?,
+            "--display-scale" => {
+                let scale: f64 = parse_value(arg, args.next())?;
+                if !(scale.is_finite() && scale > 0.0) {
+                    return Err(format!("invalid {} value : {}", arg, scale));
+                }
+                set_layout(&mut options, "--display-scale", Layout::Scaled(scale))?;
+            }
+            "--round-decimals" => {
+                let decimals: u32 = parse_value(arg, args.next())?;
+                if decimals > MAX_ROUND_DECIMALS {
+                    return Err(format!("invalid {} value : {}", arg, decimals));
+                }
+                set_layout(&mut options, "--round-decimals", Layout::Rounded(decimals))?;
+            }
+            "--disputed" => set_layout(&mut options, "--disputed", Layout::Disputed)?,
+            "--event-counts" => set_layout(&mut options, "--event-counts", Layout::EventCounts)?,
+            "--lock-reason" => set_layout(&mut options, "--lock-reason", Layout::LockReason)?,
+            "--run-metadata" => options.run_metadata = true,
+            "--summary" => options.summary = true,
+            "--error-on-empty" => options.error_on_empty = true,
+            "--format" => {
+                let format = parse_value(arg, args.next())?;
+                set_layout(&mut options, "--format", Layout::Formatted(format))?;
+            }
+            "--amount-backend" => options.amount_backend = parse_value(arg, args.next())?,
+            "--locked-style" => {
+                let style = parse_value(arg, args.next())?;
+                set_layout(&mut options, "--locked-style", Layout::LockedAs(style))?;
+            }
+            "--flush-every" => {
+                let flush_every = parse_value(arg, args.next())?;
+                set_layout(&mut options, "--flush-every", Layout::Chunked(flush_every))?;
+            }
+            "--resolve" => {
+                let path = args.next().ok_or("--resolve requires a csv path")?;
+                options.resolve = Some(path.clone());
+            }
+            "--since" => {
+                let path = args.next().ok_or("--since requires a snapshot path")?;
+                options.snapshot = Some(path.clone());
+            }
+            "--audit" => {
+                let path = args.next().ok_or("--audit requires an output path")?;
+                config.record_audit_trail = true;
+                options.audit_trail = Some(path.clone());
+            }
+            "--warnings-out" => {
+                let path = args
+                    .next()
+                    .ok_or("--warnings-out requires an output path")?;
+                config.record_rejections = true;
+                options.warnings_out = Some(path.clone());
+            }
+            #[cfg(feature = "sqlite")]
+            "--sqlite" => {
+                let path = args.next().ok_or("--sqlite requires a database path")?;
+                options.sqlite = Some(path.clone());
+            }
+            _ if !arg.starts_with("--") => options.files.push(arg.clone()),
+            _ => return Err(format!("unknown option : {}", arg)),
         }
-        false
     }
-    /// Deduct withdrawal amount from an Account.
-    /// Ignore withdrawal request from an account with insufficient available funds.
-    fn withdrawal(&mut self, record: &Transaction) -> bool {
-        if self.available >= record.amount.unwrap_or(0.0)
-            && !self.locked
-            && self.client == record.client
-        {
-            self.available -= record.amount.unwrap_or(0.0);
-            self.total -= record.amount.unwrap_or(0.0);
-            return true;
+    // another backend or `--stream` only writes the plain accounts of the ledger it builds.
+    let layouts: Vec<&'static str> = options.layout_flag.into_iter().collect();
+    let mut conflicts = [layouts, whole_ledger_flags(&options)].concat();
+    if options.amount_backend != AmountBackend::F32 {
+        if !conflicts.is_empty() {
+            return Err(format!(
+                "--amount-backend cannot be combined with {}",
+                conflicts.join(", ")
+            ));
         }
-        false
+        conflicts.push("--amount-backend");
     }
-    /// Held funds from a disputed transaction.
-    fn dispute(&mut self, record: &Transaction) -> bool {
-        if record.r#type == TxType::Withdrawal
-            || record.r#type == TxType::Deposit && !self.locked && self.client == record.client
-        {
-            self.held += record.amount.unwrap_or(0.0);
-            self.available -= record.amount.unwrap_or(0.0);
-            return true;
-        }
-        false
+    if options.stream && !conflicts.is_empty() {
+        return Err(format!(
+            "--stream cannot be combined with {}",
+            conflicts.join(", ")
+        ));
     }
-    /// Add resolved amount from a resolved transaction.
-    fn resolve(&mut self, record: &Transaction) -> bool {
-        if record.r#type == TxType::Dispute && !self.locked && self.client == record.client {
-            self.held -= record.amount.unwrap_or(0.0);
-            self.available += record.amount.unwrap_or(0.0);
-            return true;
+    Ok(options)
+}
+
+/// Names of the flags set among `flags`.
+fn set_flags<const N: usize>(flags: [(&'static str, bool); N]) -> Vec<&'static str> {
+    flags
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(flag, _)| flag)
+        .collect()
+}
+
+/// Flags reading the whole f32 ledger once the input is over, i.e after `--stream` dropped it.
+fn whole_ledger_flags(options: &Options) -> Vec<&'static str> {
+    set_flags([
+        ("--since", options.snapshot.is_some()),
+        ("--resolve", options.resolve.is_some()),
+        ("--audit", options.audit_trail.is_some()),
+        ("--warnings-out", options.warnings_out.is_some()),
+        ("--run-metadata", options.run_metadata),
+        ("--summary", options.summary),
+        #[cfg(feature = "sqlite")]
+        ("--sqlite", options.sqlite.is_some()),
+    ])
+}
+
+/// Parse the value following a command line flag.
+fn parse_value<T: FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or(format!("{} requires a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid {} value : {}", flag, value))
+}
+
+/// Process the input until EOF then write and flush the final ledger,
+/// reporting only accounts changed since the snapshot when one is given.
+/// Returns the number of accounts in the final ledger and of transactions processed.
+fn report<W: Write>(
+    file: &str,
+    out: &mut W,
+    options: Options,
+) -> Result<(usize, usize), LedgerError> {
+    let files: Vec<&str> = [file]
+        .into_iter()
+        .chain(options.files.iter().map(String::as_str))
+        .collect();
+    if options.amount_backend != AmountBackend::F32 {
+        let mut ledger = DynLedger::new(options.amount_backend, options.config);
+        ledger.process_files(&files)?;
+        ledger.write_accounts(out)?;
+        out.flush()?;
+        return Ok((ledger.len(), ledger.processed()));
+    }
+    if options.stream {
+        let mut ledger = Ledger::new(options.config);
+        let written = ledger.stream_files(&files, out)?;
+        out.flush()?;
+        return Ok((written, ledger.processed()));
+    }
+    let (mut ledger, baseline) = match &options.snapshot {
+        Some(snapshot) => {
+            let baseline = load_snapshot(File::open(snapshot)?)?;
+            let ledger = Ledger::with_accounts(baseline.clone(), options.config);
+            (ledger, Some(baseline))
         }
-        false
+        None => (Ledger::new(options.config), None),
+    };
+    ledger.process_files(&files)?;
+    if let Some(path) = &options.resolve {
+        ledger.resolve_batch(File::open(path)?)?;
+    }
+    let accounts = match &baseline {
+        Some(baseline) => changed_accounts(baseline, ledger.accounts()),
+        None => ledger.accounts().values().collect(),
+    };
+    // comment lines would break the json formats.
+    let comments = options.layout.allows_comments();
+    if options.run_metadata && comments {
+        let generated = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        write_run_metadata(out, &files.join(" "), ledger.processed(), generated)?;
     }
-    /// Deduct a disputed transaction amount.
-    /// Accept only disputed transaction, else ignore request.
-    fn chargeback(&mut self, record: &Transaction) -> bool {
-        if record.r#type == TxType::Dispute && self.client == record.client {
-            self.locked = true;
-            self.total -= record.amount.unwrap_or(0.0);
-            self.held -= record.amount.unwrap_or(0.0);
-            return true;
+    let summarized = (options.summary && comments).then(|| accounts.clone());
+    match options.layout {
+        Layout::Plain => write_accounts(out, accounts)?,
+        Layout::Verify => {
+            write_verified_accounts(out, accounts)?;
         }
-        false
+        Layout::Disputed => write_disputed_accounts(out, accounts, &ledger.disputed_totals())?,
+        Layout::EventCounts => write_event_count_accounts(out, accounts, ledger.activities())?,
+        Layout::LockReason => write_lock_reason_accounts(out, accounts)?,
+        Layout::MinorUnits => write_minor_units_accounts(out, accounts)?,
+        Layout::Scaled(scale) => write_scaled_accounts(out, accounts, scale)?,
+        Layout::Rounded(decimals) => write_reconciled_accounts(out, accounts, decimals)?,
+        Layout::Chunked(flush_every) => write_accounts_chunked(out, accounts, flush_every)?,
+        Layout::LockedAs(style) => write_accounts_locked_as(out, accounts, style)?,
+        Layout::Formatted(format) => write_formatted_accounts(out, accounts, format)?,
+    }
+    if let Some(accounts) = summarized {
+        write_summary(out, accounts)?;
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &options.sqlite {
+        let mut conn = rusqlite::Connection::open(path)?;
+        mini_ledger::write_sqlite(&mut conn, ledger.accounts().values())?;
+    }
+    if let Some(path) = &options.audit_trail {
+        write_audit_trail(File::create(path)?, ledger.audit_trail())?;
+    }
+    if let Some(path) = &options.warnings_out {
+        write_rejections(File::create(path)?, ledger.rejections())?;
     }
+    out.flush()?;
+    Ok((ledger.accounts().len(), ledger.processed()))
 }
 
-/// Add new client to the ledger , only deposit tnx are valid.
-/// otherwise account is open with 0 funds.
-fn create_new_account(record: &Transaction) -> Account {
-    let total = match record.r#type {
-        TxType::Deposit => record.amount.unwrap_or(0.0),
-        _ => 0.0,
-    };
-    Account {
-        client: record.client,
-        available: total,
-        held: 0.0,
-        total,
-        locked: false,
+/// Printed when the input file argument is missing.
+const USAGE: &str = "usage : mini_ledger <transactions.csv | directory | -> [options]";
+
+/// Reasons the command line run fails.
+#[derive(Debug)]
+enum CliError {
+    /// No input file argument, carries the usage text.
+    Usage(&'static str),
+    Options(String),
+    Ledger(LedgerError),
+    /// The output was written but holds no account, only raised with `--error-on-empty`.
+    /// Carries whether the input held transactions, all of them rejected then.
+    EmptyLedger {
+        rejected_all: bool,
+    },
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Usage(usage) => write!(f, "missing input file\n{}", usage),
+            CliError::Options(err) => write!(f, "error parsing options : {}", err),
+            CliError::Ledger(err) => write!(f, "error processing records : {}", err),
+            CliError::EmptyLedger { rejected_all: true } => {
+                write!(f, "ledger is empty, every transaction was rejected")
+            }
+            CliError::EmptyLedger {
+                rejected_all: false,
+            } => {
+                write!(f, "ledger is empty, no transactions in input")
+            }
+        }
     }
 }
-/// Reads csv file prints out ledger final state.
-fn process_records(csv: &String) -> Result<HashMap<u16, Account>, Box<dyn Error>> {
-    let mut tx_history: HashMap<u32, Transaction> = HashMap::new();
-    let mut ledger: HashMap<u16, Account> = HashMap::new();
-    let mut rdr = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(csv)?;
-    let mut successful = false;
-    for result in rdr.deserialize() {
-        let mut record: Transaction = result?;
-        ledger
-            .entry(record.client)
-            .and_modify(|account| {
-                // fetch the referenced tx data for special tx type and verify the client id.
-
-                let transaction = match record.r#type {
-                    TxType::Dispute | TxType::Resolve | TxType::Chargeback => {
-                        tx_history.get(&record.tx)
-                    }
-                    TxType::Withdrawal | TxType::Deposit => Some(&record),
-                };
-
-                if let Some(rc) = transaction {
-                    // match on incoming tx and use the correct tx data to process.
-                    successful = match record.r#type {
-                        TxType::Deposit => account.deposit(rc),
-                        TxType::Withdrawal => account.withdrawal(rc),
-                        TxType::Dispute => account.dispute(rc),
-                        TxType::Resolve => account.resolve(rc),
-                        TxType::Chargeback => account.chargeback(rc),
-                    };
-                    // need to update the tnx amount for tnx that is missing amount.
-                    record.amount = rc.amount;
-                }
-            })
-            .or_insert_with(|| {
-                successful = true;
-                create_new_account(&record)
-            });
-        // only update / insert successful transactions
-        if successful {
-            tx_history.insert(record.tx, record);
+
+impl CliError {
+    /// Process exit status, an empty ledger gets its own so pipelines can tell it apart.
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::EmptyLedger { .. } => 3,
+            CliError::Usage(_) | CliError::Options(_) | CliError::Ledger(_) => 1,
         }
     }
-    Ok(ledger)
+}
+
+impl From<LedgerError> for CliError {
+    fn from(err: LedgerError) -> Self {
+        CliError::Ledger(err)
+    }
+}
+
+/// Parse the command line, `args[0]` being the program name, then process the input to `out`.
+fn run<W: Write>(args: &[String], out: &mut W) -> Result<(), CliError> {
+    let Some(file) = args.get(1) else {
+        return Err(CliError::Usage(USAGE));
+    };
+    let options = parse_options(&args[2..]).map_err(CliError::Options)?;
+    let error_on_empty = options.error_on_empty;
+    let (accounts, processed) = report(file, out, options)?;
+    if accounts == 0 && error_on_empty {
+        return Err(CliError::EmptyLedger {
+            rejected_all: processed > 0,
+        });
+    }
+    Ok(())
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let file = &args[1];
-
-    match process_records(file) {
-        Ok(ledger) => {
-            println!("client, available, held, total, locked");
-            ledger.values().for_each(|account| println!("{:}", account))
-        }
-        Err(err) => {
-            println!("error processing records : {}", err);
-            process::exit(1);
-        }
+    // buffer the rows so large ledgers are not written line by line, report flushes once done.
+    let mut out = BufWriter::new(io::stdout().lock());
+    if let Err(err) = run(&args, &mut out) {
+        println!("{}", err);
+        process::exit(err.exit_code());
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::error::Error;
+
+    use mini_ledger::{generate_transactions, write_transactions, GeneratorConfig};
+
     use super::*;
 
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
     #[test]
-    fn test_dispute() -> Result<(), Box<dyn Error>> {
-        let ledger = process_records(&"src/tests/input/dispute.csv".to_string()).unwrap();
-        assert_eq!(ledger[&1].available, -1.0);
-        assert_eq!(ledger[&1].held, 11.5);
-        assert_eq!(ledger[&1].total, 10.5);
-        assert!(!ledger[&1].locked);
+    fn test_missing_input_file() {
+        let err = run(&args(&["mini_ledger"]), &mut Vec::new()).unwrap_err();
+        assert!(matches!(err, CliError::Usage(_)));
+        assert!(err.to_string().starts_with("missing input file"));
+        assert!(err.to_string().contains(USAGE));
+    }
+
+    #[test]
+    fn test_run() -> Result<(), Box<dyn Error>> {
+        let mut out = Vec::new();
+        let dispute = ["mini_ledger", "src/tests/input/dispute.csv", "--disputed"];
+        run(&args(&dispute), &mut out).map_err(|err| err.to_string())?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "client, available, held, total, locked, disputed\n1,-1.0000,11.5000,10.5000,false,11.5000\n"
+        );
+
+        let bogus = ["mini_ledger", "src/tests/input/dispute.csv", "--bogus"];
+        let result = run(&args(&bogus), &mut Vec::new());
+        assert!(matches!(result, Err(CliError::Options(_))));
         Ok(())
     }
 
     #[test]
-    fn test_chargeback() -> Result<(), Box<dyn Error>> {
-        let ledger = process_records(&"src/tests/input/chargeback.csv".to_string()).unwrap();
-        assert_eq!(ledger[&2].available, -3.0);
-        assert_eq!(ledger[&2].held, 0.0);
-        assert_eq!(ledger[&2].total, -3.0);
-        assert!(ledger[&2].locked);
+    fn test_output_flag_combinations() -> Result<(), Box<dyn Error>> {
+        let mut out = Vec::new();
+        let json = [
+            "mini_ledger",
+            "src/tests/input/mixed.csv",
+            "--format",
+            "json",
+            "--run-metadata",
+            "--summary",
+        ];
+        run(&args(&json), &mut out).map_err(|err| err.to_string())?;
+        let accounts: serde_json::Value = serde_json::from_slice(&out)?;
+        assert_eq!(accounts.as_array().map(Vec::len), Some(7));
+
+        let disputed = ["mini_ledger", "src/tests/input/mixed.csv", "--disputed"];
+        let combined = [&disputed[..], &["--format", "json"]].concat();
+        let err = run(&args(&combined), &mut Vec::new()).unwrap_err();
+        assert!(matches!(err, CliError::Options(_)));
+        assert!(err
+            .to_string()
+            .ends_with("--disputed, --format cannot be combined"));
+        let backend = [&disputed[..], &["--amount-backend", "fixed"]].concat();
+        assert!(matches!(
+            run(&args(&backend), &mut Vec::new()),
+            Err(CliError::Options(_))
+        ));
         Ok(())
     }
+
     #[test]
-    fn test_resolved() -> Result<(), Box<dyn Error>> {
-        let ledger = process_records(&"src/tests/input/resolve.csv".to_string()).unwrap();
-        assert_eq!(ledger[&1].available, 0.5);
-        assert_eq!(ledger[&1].held, 0.0);
-        assert_eq!(ledger[&1].total, 0.5);
-        assert!(!ledger[&1].locked);
+    fn test_stream() -> Result<(), Box<dyn Error>> {
+        let mut out = Vec::new();
+        let stream = [
+            "mini_ledger",
+            "src/tests/input/sorted_by_client.csv",
+            "--stream",
+        ];
+        run(&args(&stream), &mut out).map_err(|err| err.to_string())?;
+        assert_eq!(String::from_utf8(out)?.lines().count(), 5);
+
+        let summary = [&stream[..], &["--summary", "--since", "snapshot.csv"]].concat();
+        let err = run(&args(&summary), &mut Vec::new()).unwrap_err();
+        assert!(matches!(err, CliError::Options(_)));
+        assert!(err.to_string().ends_with("--since, --summary"));
         Ok(())
     }
+
     #[test]
-    fn test_withdrawal() -> Result<(), Box<dyn Error>> {
-        let ledger = process_records(&"src/tests/input/withdrawal.csv".to_string()).unwrap();
-        assert_eq!(ledger[&1].available, 10.0);
-        assert_eq!(ledger[&1].held, 0.0);
-        assert!(!ledger[&1].locked);
+    fn test_buffered_output_is_complete() -> Result<(), Box<dyn Error>> {
+        let path =
+            std::env::temp_dir().join(format!("mini_ledger_every_client_{}.csv", process::id()));
+        let records = generate_transactions(&GeneratorConfig::deposit_per_client(u16::MAX));
+        write_transactions(File::create(&path)?, &records)?;
+        // the same buffering as `main`, the rows only reach the output once `report` flushes.
+        let mut out = BufWriter::new(Vec::new());
+        let ran = run(&args(&["mini_ledger", &path.to_string_lossy()]), &mut out);
+        std::fs::remove_file(&path)?;
+        ran.map_err(|err| err.to_string())?;
+        let output = String::from_utf8(out.into_inner()?)?;
+        assert_eq!(output.lines().count(), u16::MAX as usize + 1);
         Ok(())
     }
+
     #[test]
-    fn test_mixed() -> Result<(), Box<dyn Error>> {
-        let ledger = process_records(&"src/tests/input/mixed.csv".to_string()).unwrap();
-        let expect_results = vec![
-            Account {
-                client: 1,
-                available: 199.0,
-                held: 0.0,
-                total: 199.0,
-                locked: true,
-            },
-            Account {
-                client: 2,
-                available: 102.0,
-                held: 0.0,
-                total: 102.0,
-                locked: false,
-            },
-            Account {
-                client: 3,
-                available: 200.0,
-                held: 100.0,
-                total: 300.0,
-                locked: false,
-            },
-            Account {
-                client: 4,
-                available: 221.0,
-                held: 0.0,
-                total: 221.0,
-                locked: false,
-            },
-            Account {
-                client: 5,
-                available: 241.0,
-                total: 241.0,
-                held: 0.0,
-                locked: false,
-            },
-            Account {
-                client: 6,
-                available: 342.0,
-                total: 342.0,
-                held: 0.0,
-                locked: false,
-            },
-            Account {
-                client: 7,
-                available: 134.0,
-                total: 134.0,
-                held: 0.0,
-                locked: false,
-            },
+    fn test_error_on_empty() -> Result<(), Box<dyn Error>> {
+        let mut out = Vec::new();
+        let rejected = [
+            "mini_ledger",
+            "src/tests/input/dust.csv",
+            "--min-deposit",
+            "1.0",
+            "--error-on-empty",
         ];
-        expect_results.iter().for_each(|ac| {
-            assert_eq!(ledger[&ac.client].available, ac.available);
-            assert_eq!(ledger[&ac.client].held, ac.held);
-            assert_eq!(ledger[&ac.client].total, ac.total);
-            assert_eq!(ledger[&ac.client].locked, ac.locked);
-        });
+        let err = run(&args(&rejected), &mut out).unwrap_err();
+        assert!(matches!(err, CliError::EmptyLedger { rejected_all: true }));
+        assert_eq!(err.exit_code(), 3);
+        assert!(err.to_string().ends_with("every transaction was rejected"));
+        // the header is still written before failing.
+        assert_eq!(
+            String::from_utf8(out)?,
+            "client, available, held, total, locked\n"
+        );
+
+        let header_only = ["mini_ledger", "src/tests/input/header_only.csv"];
+        run(&args(&header_only), &mut Vec::new()).map_err(|err| err.to_string())?;
+        let flagged = [&header_only[..], &["--error-on-empty"]].concat();
+        let err = run(&args(&flagged), &mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err,
+            CliError::EmptyLedger {
+                rejected_all: false
+            }
+        ));
+        assert_eq!(err.to_string(), "ledger is empty, no transactions in input");
+        Ok(())
+    }
+
+    /// Run the cli over `src/tests/input/<name>.csv` and compare the exact output to
+    /// `src/tests/golden/<name>.csv`, rewriting the golden file instead when `UPDATE_GOLDEN` is set.
+    fn assert_golden(name: &str) -> Result<(), Box<dyn Error>> {
+        let input = format!("src/tests/input/{}.csv", name);
+        let golden = format!("src/tests/golden/{}.csv", name);
+        let mut out = Vec::new();
+        run(&args(&["mini_ledger", &input]), &mut out).map_err(|err| err.to_string())?;
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&golden, &out)?;
+        }
+        assert_eq!(
+            String::from_utf8(out)?,
+            std::fs::read_to_string(&golden)?,
+            "output of {} differs from {}",
+            input,
+            golden
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_golden_outputs() -> Result<(), Box<dyn Error>> {
+        for name in ["mixed", "chargeback", "resolve"] {
+            assert_golden(name)?;
+        }
         Ok(())
     }
 }
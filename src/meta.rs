@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+/// Provenance read from a leading `# key: value; key: value` comment line of the input.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LedgerMeta {
+    /// `ledger-version` entry.
+    pub version: Option<u32>,
+    /// `currency` entry.
+    pub currency: Option<String>,
+    /// Any other entries, keyed by name.
+    pub extra: HashMap<String, String>,
+}
+
+impl LedgerMeta {
+    /// Parse a metadata comment line, returns `None` for lines that are not comments.
+    pub fn parse(line: &str) -> Option<LedgerMeta> {
+        let body = line.trim().strip_prefix('#')?;
+        let mut meta = LedgerMeta::default();
+        for entry in body.split(';') {
+            let Some((key, value)) = entry.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "ledger-version" => match value.parse() {
+                    Ok(version) => meta.version = Some(version),
+                    Err(_) => eprintln!("ignoring invalid ledger-version : {}", value),
+                },
+                "currency" => meta.currency = Some(value.to_string()),
+                _ => {
+                    meta.extra.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+        Some(meta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_meta() {
+        let meta = LedgerMeta::parse("# ledger-version: 1; currency: USD; source: bank-x").unwrap();
+        assert_eq!(meta.version, Some(1));
+        assert_eq!(meta.currency.as_deref(), Some("USD"));
+        assert_eq!(meta.extra["source"], "bank-x");
+        assert_eq!(LedgerMeta::parse("type,client,tx,amount"), None);
+    }
+}
@@ -0,0 +1,958 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::account::{Account, LockReason};
+use crate::activity::ClientActivity;
+use crate::amount::Amount;
+#[cfg(feature = "csv")]
+use crate::error::LedgerError;
+use crate::ledger::Ledger;
+#[cfg(feature = "csv")]
+use crate::ledger::Rejection;
+#[cfg(feature = "csv")]
+use crate::transaction::Transaction;
+
+/// Header row of the regular account output, extended by the layouts adding columns.
+pub(crate) const ACCOUNTS_HEADER: &str = "client, available, held, total, locked";
+
+/// Writes the output header followed by one line per account.
+pub fn write_accounts<'a, W: Write, A: Amount + 'a>(
+    out: &mut W,
+    accounts: impl IntoIterator<Item = &'a Account<A>>,
+) -> io::Result<()> {
+    writeln!(out, "{}", ACCOUNTS_HEADER)?;
+    for account in accounts {
+        writeln!(out, "{:}", account)?;
+    }
+    Ok(())
+}
+
+/// Layout the final accounts are written in.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// The regular `client, available, held, total, locked` csv.
+    #[default]
+    Csv,
+    /// A single json array of accounts.
+    Json,
+    /// One json account object per line.
+    Ndjson,
+    /// Aligned columns for reading in a terminal.
+    Table,
+    /// Prometheus text exposition gauges, i.e `ledger_available{client="1"} 1.5000`.
+    Prometheus,
+}
+
+impl OutputFormat {
+    /// Whether `#` comment lines, i.e the run metadata or the summary, can be added to the output.
+    pub fn allows_comments(self) -> bool {
+        !matches!(self, OutputFormat::Json | OutputFormat::Ndjson)
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "table" => Ok(OutputFormat::Table),
+            "prometheus" => Ok(OutputFormat::Prometheus),
+            _ => Err(format!("unknown output format : {}", format)),
+        }
+    }
+}
+
+/// Writes every ledger account, sorted by client, in the given format.
+pub fn write_ledger<A: Amount, W: Write>(
+    ledger: &Ledger<A>,
+    out: &mut W,
+    format: OutputFormat,
+) -> io::Result<()> {
+    write_formatted_accounts(out, ledger.accounts().values(), format)
+}
+
+/// Writes the accounts in the given format, i.e only those changed since a snapshot.
+pub fn write_formatted_accounts<'a, W: Write, A: Amount + 'a>(
+    out: &mut W,
+    accounts: impl IntoIterator<Item = &'a Account<A>>,
+    format: OutputFormat,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Csv => write_accounts(out, accounts),
+        OutputFormat::Json => {
+            let accounts: Vec<&Account<A>> = accounts.into_iter().collect();
+            serde_json::to_writer(&mut *out, &accounts)?;
+            writeln!(out)
+        }
+        OutputFormat::Ndjson => {
+            for account in accounts {
+                serde_json::to_writer(&mut *out, account)?;
+                writeln!(out)?;
+            }
+            Ok(())
+        }
+        OutputFormat::Table => {
+            let row = |out: &mut W, columns: [&str; 5]| {
+                let [client, available, held, total, locked] = columns;
+                writeln!(
+                    out,
+                    "{:>6} {:>14} {:>14} {:>14} {:>6}",
+                    client, available, held, total, locked
+                )
+            };
+            row(out, ["client", "available", "held", "total", "locked"])?;
+            for account in accounts {
+                row(
+                    out,
+                    [
+                        &account.client.to_string(),
+                        &account.available.format(),
+                        &account.held.format(),
+                        &account.total.format(),
+                        &account.locked.to_string(),
+                    ],
+                )?;
+            }
+            Ok(())
+        }
+        OutputFormat::Prometheus => {
+            let accounts: Vec<&Account<A>> = accounts.into_iter().collect();
+            let gauges: [Gauge<A>; 4] = [
+                ("ledger_available", |account| account.available.format()),
+                ("ledger_held", |account| account.held.format()),
+                ("ledger_total", |account| account.total.format()),
+                ("ledger_locked", |account| {
+                    u8::from(account.locked).to_string()
+                }),
+            ];
+            // the exposition format expects the samples of a metric grouped after its type.
+            for (metric, value) in gauges {
+                writeln!(out, "# TYPE {} gauge", metric)?;
+                for account in &accounts {
+                    writeln!(
+                        out,
+                        "{}{{client=\"{}\"}} {}",
+                        metric,
+                        account.client,
+                        value(account)
+                    )?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Prometheus metric name and the account value it samples.
+type Gauge<A> = (&'static str, fn(&Account<A>) -> String);
+
+/// Rendering of the `locked` column expected downstream.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum LockedStyle {
+    /// `true` / `false`, as written by `write_accounts`.
+    #[default]
+    Bool,
+    /// `1` / `0`.
+    Numeric,
+    /// `yes` / `no`.
+    YesNo,
+}
+
+impl LockedStyle {
+    fn render(self, locked: bool) -> &'static str {
+        match (self, locked) {
+            (LockedStyle::Bool, true) => "true",
+            (LockedStyle::Bool, false) => "false",
+            (LockedStyle::Numeric, true) => "1",
+            (LockedStyle::Numeric, false) => "0",
+            (LockedStyle::YesNo, true) => "yes",
+            (LockedStyle::YesNo, false) => "no",
+        }
+    }
+}
+
+impl FromStr for LockedStyle {
+    type Err = String;
+
+    fn from_str(style: &str) -> Result<Self, Self::Err> {
+        match style {
+            "bool" => Ok(LockedStyle::Bool),
+            "numeric" => Ok(LockedStyle::Numeric),
+            "yes-no" => Ok(LockedStyle::YesNo),
+            _ => Err(format!("unknown locked style : {}", style)),
+        }
+    }
+}
+
+/// Writes the accounts like `write_accounts`, rendering the `locked` column in the given style.
+pub fn write_accounts_locked_as<'a, W: Write, A: Amount + 'a>(
+    out: &mut W,
+    accounts: impl IntoIterator<Item = &'a Account<A>>,
+    style: LockedStyle,
+) -> io::Result<()> {
+    writeln!(out, "{}", ACCOUNTS_HEADER)?;
+    for account in accounts {
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            account.client,
+            account.available.format(),
+            account.held.format(),
+            account.total.format(),
+            style.render(account.locked)
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the accounts with an extra `disputed` column, the part of held attributable to open disputes.
+pub fn write_disputed_accounts<'a, W: Write, A: Amount + 'a>(
+    out: &mut W,
+    accounts: impl IntoIterator<Item = &'a Account<A>>,
+    disputed: &BTreeMap<u16, A>,
+) -> io::Result<()> {
+    writeln!(out, "{}, disputed", ACCOUNTS_HEADER)?;
+    for account in accounts {
+        let amount = disputed.get(&account.client).copied().unwrap_or_default();
+        writeln!(out, "{:},{}", account, amount.format())?;
+    }
+    Ok(())
+}
+
+/// Writes the accounts with extra `deposits`, `withdrawals` and `disputes` columns counting
+/// the applied transactions of each client, i.e for risk scoring.
+pub fn write_event_count_accounts<'a, W: Write, A: Amount + 'a>(
+    out: &mut W,
+    accounts: impl IntoIterator<Item = &'a Account<A>>,
+    activity: &BTreeMap<u16, ClientActivity<A>>,
+) -> io::Result<()> {
+    writeln!(out, "{}, deposits, withdrawals, disputes", ACCOUNTS_HEADER)?;
+    for account in accounts {
+        let (deposits, withdrawals, disputes) =
+            activity.get(&account.client).map_or((0, 0, 0), |activity| {
+                (activity.deposits, activity.withdrawals, activity.disputes)
+            });
+        writeln!(
+            out,
+            "{:},{},{},{}",
+            account, deposits, withdrawals, disputes
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the accounts with an extra `lock_reason` column, empty for unlocked accounts.
+pub fn write_lock_reason_accounts<'a, W: Write, A: Amount + 'a>(
+    out: &mut W,
+    accounts: impl IntoIterator<Item = &'a Account<A>>,
+) -> io::Result<()> {
+    writeln!(out, "{}, lock_reason", ACCOUNTS_HEADER)?;
+    for account in accounts {
+        let reason = account.lock_reason.map_or("", LockReason::as_str);
+        writeln!(out, "{:},{}", account, reason)?;
+    }
+    Ok(())
+}
+
+/// Writes a `#` comment line with the run metadata, the input name, the number of processed rows
+/// and the unix time the output was generated at, in the `key: value` form read by `LedgerMeta`.
+pub fn write_run_metadata<W: Write>(
+    out: &mut W,
+    input: &str,
+    rows: usize,
+    generated: u64,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "# input: {}; rows: {}; generated: {}",
+        input, rows, generated
+    )
+}
+
+/// Writes a `#` comment footer with the number of accounts, their summed available, held
+/// and total amounts and how many are locked, in the `key: value` form read by `LedgerMeta`.
+pub fn write_summary<'a, W: Write, A: Amount + 'a>(
+    out: &mut W,
+    accounts: impl IntoIterator<Item = &'a Account<A>>,
+) -> io::Result<()> {
+    let (mut count, mut locked) = (0, 0);
+    let (mut available, mut held, mut total) = (A::default(), A::default(), A::default());
+    for account in accounts {
+        count += 1;
+        locked += usize::from(account.locked);
+        available += account.available;
+        held += account.held;
+        total += account.total;
+    }
+    writeln!(
+        out,
+        "# accounts: {}; available: {}; held: {}; total: {}; locked: {}",
+        count,
+        available.format(),
+        held.format(),
+        total.format(),
+        locked
+    )
+}
+
+/// Writes the accounts with amounts divided by `scale`, i.e in thousands for `1000`,
+/// the header naming the unit. Only the rendering is scaled, the balances keep their precision.
+pub fn write_scaled_accounts<'a, W: Write, A: Amount + 'a>(
+    out: &mut W,
+    accounts: impl IntoIterator<Item = &'a Account<A>>,
+    scale: f64,
+) -> io::Result<()> {
+    let unit = format!("(x{})", scale);
+    writeln!(
+        out,
+        "client, available {}, held {}, total {}, locked",
+        unit, unit, unit
+    )?;
+    let scaled = |amount: A| format!("{:.4}", amount.to_f64() / scale);
+    for account in accounts {
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            account.client,
+            scaled(account.available),
+            scaled(account.held),
+            scaled(account.total),
+            account.locked
+        )?;
+    }
+    Ok(())
+}
+
+/// Ten-thousandths per unit, matching the 4 decimals of the regular output.
+const MINOR_UNITS: f64 = 10_000.0;
+
+/// Amount as an integer number of ten-thousandths.
+fn to_minor_units<A: Amount>(amount: A) -> i64 {
+    (amount.to_f64() * MINOR_UNITS).round() as i64
+}
+
+/// Writes the accounts with amounts as integer ten-thousandths, i.e `100.5000` as `1005000`.
+pub fn write_minor_units_accounts<'a, W: Write, A: Amount + 'a>(
+    out: &mut W,
+    accounts: impl IntoIterator<Item = &'a Account<A>>,
+) -> io::Result<()> {
+    writeln!(out, "{}", ACCOUNTS_HEADER)?;
+    for account in accounts {
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            account.client,
+            to_minor_units(account.available),
+            to_minor_units(account.held),
+            to_minor_units(account.total),
+            account.locked
+        )?;
+    }
+    Ok(())
+}
+
+/// Round the amounts to integer units of `10^-decimals` with the largest remainder method,
+/// so they sum to their exact sum rounded. Every amount is floored, then the units lost to
+/// flooring go one each to the amounts with the largest remainders, ties to the earliest.
+fn round_preserving_sum(amounts: &[f64], decimals: u32) -> Vec<i64> {
+    let factor = 10f64.powi(decimals as i32);
+    let scaled: Vec<f64> = amounts.iter().map(|amount| amount * factor).collect();
+    let mut units: Vec<i64> = scaled.iter().map(|amount| amount.floor() as i64).collect();
+    let target = scaled.iter().sum::<f64>().round() as i64;
+    let missing = target - units.iter().sum::<i64>();
+    let mut by_remainder: Vec<usize> = (0..scaled.len()).collect();
+    by_remainder.sort_by(|a, b| {
+        let remainder = |index: usize| scaled[index] - scaled[index].floor();
+        remainder(*b).total_cmp(&remainder(*a))
+    });
+    for index in by_remainder.into_iter().take(missing.max(0) as usize) {
+        units[index] += 1;
+    }
+    units
+}
+
+/// Render integer units of `10^-decimals` as a decimal amount.
+fn format_units(units: i64, decimals: u32) -> String {
+    let sign = if units < 0 { "-" } else { "" };
+    let (units, factor) = (units.unsigned_abs(), 10u64.pow(decimals));
+    if decimals == 0 {
+        return format!("{}{}", sign, units);
+    }
+    format!(
+        "{}{}.{:0width$}",
+        sign,
+        units / factor,
+        units % factor,
+        width = decimals as usize
+    )
+}
+
+/// Writes the accounts with amounts rounded to `decimals`, each column reconciled with the
+/// largest remainder method so its rows add up to the column total, written as a `#` footer.
+/// `decimals` is meant for display precisions, at most 9.
+/// Columns are reconciled independently, a row's available and held may not add up to its total.
+pub fn write_reconciled_accounts<'a, W: Write, A: Amount + 'a>(
+    out: &mut W,
+    accounts: impl IntoIterator<Item = &'a Account<A>>,
+    decimals: u32,
+) -> io::Result<()> {
+    let accounts: Vec<&Account<A>> = accounts.into_iter().collect();
+    let column = |amount: fn(&Account<A>) -> A| {
+        let amounts: Vec<f64> = accounts
+            .iter()
+            .map(|account| amount(account).to_f64())
+            .collect();
+        round_preserving_sum(&amounts, decimals)
+    };
+    let available = column(|account| account.available);
+    let held = column(|account| account.held);
+    let total = column(|account| account.total);
+    writeln!(out, "{}", ACCOUNTS_HEADER)?;
+    for (index, account) in accounts.iter().enumerate() {
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            account.client,
+            format_units(available[index], decimals),
+            format_units(held[index], decimals),
+            format_units(total[index], decimals),
+            account.locked
+        )?;
+    }
+    let sum = |units: &[i64]| format_units(units.iter().sum(), decimals);
+    writeln!(
+        out,
+        "# available: {}; held: {}; total: {}",
+        sum(&available),
+        sum(&held),
+        sum(&total)
+    )
+}
+
+/// Writes the accounts like `write_accounts`, flushing the writer every `flush_every` rows
+/// and once more at the end.
+pub fn write_accounts_chunked<'a, W: Write, A: Amount + 'a>(
+    out: &mut W,
+    accounts: impl IntoIterator<Item = &'a Account<A>>,
+    flush_every: usize,
+) -> io::Result<()> {
+    writeln!(out, "{}", ACCOUNTS_HEADER)?;
+    for (index, account) in accounts.into_iter().enumerate() {
+        writeln!(out, "{:}", account)?;
+        if (index + 1) % flush_every.max(1) == 0 {
+            out.flush()?;
+        }
+    }
+    out.flush()
+}
+
+/// Writes active accounts to `active_out` and locked accounts to `locked_out`, both sorted by client.
+pub fn write_split<A: Amount, W: Write, L: Write>(
+    ledger: &Ledger<A>,
+    active_out: &mut W,
+    locked_out: &mut L,
+) -> io::Result<()> {
+    let (locked, active): (Vec<&Account<A>>, Vec<&Account<A>>) = ledger
+        .accounts()
+        .values()
+        .partition(|account| account.locked);
+    write_accounts(active_out, active)?;
+    write_accounts(locked_out, locked)
+}
+
+/// Largest difference between stored and recomputed available still considered consistent.
+const VERIFY_TOLERANCE: f32 = 0.00005;
+
+/// Writes accounts with available recomputed as `total - held`,
+/// flagging and returning the clients whose stored available disagrees.
+pub fn write_verified_accounts<'a, W: Write, A: Amount + 'a>(
+    out: &mut W,
+    accounts: impl IntoIterator<Item = &'a Account<A>>,
+) -> io::Result<Vec<u16>> {
+    writeln!(out, "{}", ACCOUNTS_HEADER)?;
+    let tolerance = A::from_f32(VERIFY_TOLERANCE);
+    let mut inconsistent = Vec::new();
+    for account in accounts {
+        let available = account.total - account.held;
+        let difference = if available > account.available {
+            available - account.available
+        } else {
+            account.available - available
+        };
+        if difference > tolerance {
+            eprintln!(
+                "inconsistent account {} : available is {} but total - held is {}",
+                account.client,
+                account.available.format(),
+                available.format()
+            );
+            inconsistent.push(account.client);
+        }
+        let verified = Account {
+            available,
+            ..account.clone()
+        };
+        writeln!(out, "{:}", verified)?;
+    }
+    Ok(inconsistent)
+}
+
+/// Writes the applied transactions, memos included, as csv rows.
+#[cfg(feature = "csv")]
+pub fn write_audit_trail<W: Write, A: Amount>(
+    out: W,
+    trail: &[Transaction<A>],
+) -> Result<(), LedgerError> {
+    let mut wtr = csv::Writer::from_writer(out);
+    for record in trail {
+        wtr.serialize(record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes the rejected transactions as a `line,client,tx,type,reason` csv report.
+#[cfg(feature = "csv")]
+pub fn write_rejections<W: Write>(out: W, rejections: &[Rejection]) -> Result<(), LedgerError> {
+    let mut wtr = csv::Writer::from_writer(out);
+    for rejection in rejections {
+        wtr.serialize(rejection)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::fixed::FixedPoint;
+    use crate::input::process_records;
+    use crate::ledger::Config;
+
+    fn synthetic_accounts(count: u16) -> BTreeMap<u16, Account> {
+        (0..count)
+            .map(|client| {
+                let account = Account {
+                    client,
+                    available: client as f32,
+                    held: 1.5,
+                    total: client as f32 + 1.5,
+                    locked: client % 7 == 0,
+                    lock_reason: None,
+                };
+                (client, account)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_verify_flags_inconsistent_account() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/dispute.csv", &Config::default())?;
+        let corrupted = Account {
+            client: 9,
+            available: 5.0,
+            held: 1.0,
+            total: 10.0,
+            locked: false,
+            lock_reason: None,
+        };
+        let mut out = Vec::new();
+        let inconsistent =
+            write_verified_accounts(&mut out, ledger.accounts().values().chain([&corrupted]))?;
+        assert_eq!(inconsistent, vec![9]);
+        let output = String::from_utf8(out)?;
+        assert!(output.contains("1,-1.0000,11.5000,10.5000,false"));
+        assert!(output.contains("9,9.0000,1.0000,10.0000,false"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_split() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        let mut active = Vec::new();
+        let mut locked = Vec::new();
+        write_split(&ledger, &mut active, &mut locked)?;
+
+        let active = String::from_utf8(active)?;
+        let clients: Vec<&str> = active
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').next().unwrap())
+            .collect();
+        assert_eq!(clients, vec!["2", "3", "4", "5", "6", "7"]);
+        assert_eq!(
+            String::from_utf8(locked)?,
+            "client, available, held, total, locked\n1,199.0000,0.0000,199.0000,true\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_minor_units_output() -> Result<(), Box<dyn Error>> {
+        let account = Account {
+            client: 1,
+            available: 100.5,
+            held: 0.0001,
+            total: 100.5001,
+            locked: false,
+            lock_reason: None,
+        };
+        let mut out = Vec::new();
+        write_minor_units_accounts(&mut out, [&account])?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "client, available, held, total, locked\n1,1005000,1,1005001,false\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_is_reproducible() -> Result<(), Box<dyn Error>> {
+        let mut runs = Vec::new();
+        for _ in 0..2 {
+            let ledger = process_records("src/tests/input/mixed.csv", &Config::default())?;
+            let mut out = Vec::new();
+            write_accounts(&mut out, ledger.accounts().values())?;
+            runs.push(out);
+        }
+        assert_eq!(runs[0], runs[1]);
+        let output = String::from_utf8(runs.remove(0))?;
+        assert!(output
+            .lines()
+            .nth(1)
+            .is_some_and(|line| line.starts_with("1,")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_disputed_column() -> Result<(), Box<dyn Error>> {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 2.5);
+        ledger.deposit(2, 3, 4.0);
+        ledger.dispute(1, 2);
+        let mut out = Vec::new();
+        write_disputed_accounts(
+            &mut out,
+            ledger.accounts().values(),
+            &ledger.disputed_totals(),
+        )?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "client, available, held, total, locked, disputed\n\
+             1,10.0000,2.5000,12.5000,false,2.5000\n\
+             2,4.0000,0.0000,4.0000,false,0.0000\n"
+        );
+        Ok(())
+    }
+
+    /// Writer recording the bytes written and how many times it was flushed.
+    #[derive(Default)]
+    struct FlushCounter {
+        written: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_accounts_chunked() -> Result<(), Box<dyn Error>> {
+        let accounts = synthetic_accounts(10);
+        let mut out = FlushCounter::default();
+        write_accounts_chunked(&mut out, accounts.values(), 3)?;
+        // after rows 3, 6 and 9, then the final flush.
+        assert_eq!(out.flushes, 4);
+
+        let mut expected = Vec::new();
+        write_accounts(&mut expected, accounts.values())?;
+        assert_eq!(out.written, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_metadata() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        let mut out = Vec::new();
+        write_run_metadata(&mut out, "mixed.csv", ledger.processed(), 1700000000)?;
+        let line = String::from_utf8(out)?;
+        assert_eq!(
+            line,
+            "# input: mixed.csv; rows: 19; generated: 1700000000\n"
+        );
+        let meta = crate::meta::LedgerMeta::parse(&line).unwrap();
+        assert_eq!(meta.extra["rows"], "19");
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_ledger_formats() -> Result<(), Box<dyn Error>> {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 1.5);
+        ledger.deposit(2, 2, 2.0);
+        ledger.dispute(2, 2);
+        let written = |format| -> Result<String, Box<dyn Error>> {
+            let mut out = Vec::new();
+            write_ledger(&ledger, &mut out, format)?;
+            Ok(String::from_utf8(out)?)
+        };
+
+        let mut csv = Vec::new();
+        write_accounts(&mut csv, ledger.accounts().values())?;
+        assert_eq!(written(OutputFormat::Csv)?, String::from_utf8(csv)?);
+        assert_eq!(
+            written(OutputFormat::Json)?,
+            "[{\"client\":1,\"available\":1.5,\"held\":0.0,\"total\":1.5,\"locked\":false},\
+             {\"client\":2,\"available\":0.0,\"held\":2.0,\"total\":2.0,\"locked\":false}]\n"
+        );
+        assert_eq!(
+            written(OutputFormat::Ndjson)?,
+            "{\"client\":1,\"available\":1.5,\"held\":0.0,\"total\":1.5,\"locked\":false}\n\
+             {\"client\":2,\"available\":0.0,\"held\":2.0,\"total\":2.0,\"locked\":false}\n"
+        );
+        assert_eq!(
+            written(OutputFormat::Table)?,
+            "client      available           held          total locked\n     \
+             1         1.5000         0.0000         1.5000  false\n     \
+             2         0.0000         2.0000         2.0000  false\n"
+        );
+        assert_eq!(
+            written(OutputFormat::Prometheus)?,
+            "# TYPE ledger_available gauge\n\
+             ledger_available{client=\"1\"} 1.5000\n\
+             ledger_available{client=\"2\"} 0.0000\n\
+             # TYPE ledger_held gauge\n\
+             ledger_held{client=\"1\"} 0.0000\n\
+             ledger_held{client=\"2\"} 2.0000\n\
+             # TYPE ledger_total gauge\n\
+             ledger_total{client=\"1\"} 1.5000\n\
+             ledger_total{client=\"2\"} 2.0000\n\
+             # TYPE ledger_locked gauge\n\
+             ledger_locked{client=\"1\"} 0\n\
+             ledger_locked{client=\"2\"} 0\n"
+        );
+        assert_eq!("ndjson".parse(), Ok(OutputFormat::Ndjson));
+        assert_eq!("prometheus".parse(), Ok(OutputFormat::Prometheus));
+        assert!("xml".parse::<OutputFormat>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_numeric_locked_style() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/chargeback.csv", &Config::default())?;
+        let mut out = Vec::new();
+        write_accounts_locked_as(&mut out, ledger.accounts().values(), LockedStyle::Numeric)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "client, available, held, total, locked\n2,-3.0000,0.0000,-3.0000,1\n"
+        );
+
+        let mut default = Vec::new();
+        write_accounts_locked_as(
+            &mut default,
+            ledger.accounts().values(),
+            LockedStyle::default(),
+        )?;
+        let mut expected = Vec::new();
+        write_accounts(&mut expected, ledger.accounts().values())?;
+        assert_eq!(default, expected);
+        assert_eq!("yes-no".parse(), Ok(LockedStyle::YesNo));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejections_report() -> Result<(), Box<dyn Error>> {
+        let config = Config {
+            record_rejections: true,
+            ..Config::default()
+        };
+        let ledger = process_records("src/tests/input/rejections.csv", &config)?;
+        let mut out = Vec::new();
+        write_rejections(&mut out, ledger.rejections())?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "line,client,tx,type,reason\n\
+             3,1,2,withdrawal,\"insufficient funds, account is locked or client does not match\"\n\
+             4,2,7,dispute,referenced transaction not found\n\
+             7,1,1,resolve,transaction is not disputed\n\
+             9,1,4,deposit,account is locked or client does not match\n"
+        );
+        assert!(
+            process_records("src/tests/input/rejections.csv", &Config::default())?
+                .rejections()
+                .is_empty()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_reason_output() -> Result<(), Box<dyn Error>> {
+        let mut ledger = process_records("src/tests/input/chargeback.csv", &Config::default())?;
+        ledger.deposit(3, 10, 1.0);
+        ledger.freeze(3);
+        let mut out = Vec::new();
+        write_lock_reason_accounts(&mut out, ledger.accounts().values())?;
+        let output = String::from_utf8(out)?;
+        let mut lines = output.lines();
+        assert_eq!(
+            lines.next(),
+            Some("client, available, held, total, locked, lock_reason")
+        );
+        assert!(lines.any(|line| line == "2,-3.0000,0.0000,-3.0000,true,chargeback"));
+        assert!(output.ends_with("3,1.0000,0.0000,1.0000,true,admin_freeze\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_scaled_output() -> Result<(), Box<dyn Error>> {
+        let account = Account {
+            client: 1,
+            available: 1_500_000.0,
+            held: 250.0,
+            total: 1_500_250.0,
+            locked: false,
+            lock_reason: None,
+        };
+        let mut out = Vec::new();
+        write_scaled_accounts(&mut out, [&account], 1000.0)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "client, available (x1000), held (x1000), total (x1000), locked\n\
+             1,1500.0000,0.2500,1500.2500,false\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_summary() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        let mut out = Vec::new();
+        write_accounts(&mut out, ledger.accounts().values())?;
+        write_summary(&mut out, ledger.accounts().values())?;
+        let output = String::from_utf8(out)?;
+        assert_eq!(
+            output.lines().last(),
+            Some(
+                "# accounts: 7; available: 1439.0000; held: 100.0000; total: 1539.0000; locked: 1"
+            )
+        );
+        let meta = crate::meta::LedgerMeta::parse(output.lines().last().unwrap()).unwrap();
+        assert_eq!(meta.extra["locked"], "1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_reconciled_rounding_keeps_sums() -> Result<(), Box<dyn Error>> {
+        let mut ledger = Ledger::new(Config::default());
+        for client in 1..=3 {
+            ledger.deposit(client, client as u32, 0.3333);
+        }
+        // rounding every row on its own shows 0.99 for a 1.00 total.
+        let naive: f64 = ledger
+            .accounts()
+            .values()
+            .map(|account| format!("{:.2}", account.total).parse::<f64>().unwrap())
+            .sum();
+        assert_ne!(format!("{:.2}", naive), "1.00");
+
+        let mut out = Vec::new();
+        write_reconciled_accounts(&mut out, ledger.accounts().values(), 2)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "client, available, held, total, locked\n\
+             1,0.34,0.00,0.34,false\n\
+             2,0.33,0.00,0.33,false\n\
+             3,0.33,0.00,0.33,false\n\
+             # available: 1.00; held: 0.00; total: 1.00\n"
+        );
+        assert_eq!(
+            round_preserving_sum(&[-1.25, -0.5, 0.25], 0),
+            vec![-1, -1, 0]
+        );
+        assert_eq!(format_units(-5, 2), "-0.05");
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed_point_writers() -> Result<(), Box<dyn Error>> {
+        let mut ledger: Ledger<FixedPoint> = Ledger::with_config(Config::default());
+        ledger.deposit(1, 1, FixedPoint::from_units(12_345_000));
+        ledger.deposit(2, 2, FixedPoint::from_units(5_000));
+        let mut out = Vec::new();
+        write_scaled_accounts(&mut out, ledger.accounts().values(), 1000.0)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "client, available (x1000), held (x1000), total (x1000), locked\n\
+             1,1.2345,0.0000,1.2345,false\n\
+             2,0.0005,0.0000,0.0005,false\n"
+        );
+
+        let mut out = Vec::new();
+        write_reconciled_accounts(&mut out, ledger.accounts().values(), 1)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "client, available, held, total, locked\n\
+             1,1234.5,0.0,1234.5,false\n\
+             2,0.5,0.0,0.5,false\n\
+             # available: 1235.0; held: 0.0; total: 1235.0\n"
+        );
+
+        let mut out = Vec::new();
+        write_minor_units_accounts(&mut out, ledger.accounts().values())?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "client, available, held, total, locked\n\
+             1,12345000,0,12345000,false\n\
+             2,5000,0,5000,false\n"
+        );
+
+        let mut out = Vec::new();
+        let inconsistent = write_verified_accounts(&mut out, ledger.accounts().values())?;
+        assert!(inconsistent.is_empty());
+        assert_eq!(
+            String::from_utf8(out)?,
+            "client, available, held, total, locked\n\
+             1,1234.5000,0.0000,1234.5000,false\n\
+             2,0.5000,0.0000,0.5000,false\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_count_columns() -> Result<(), Box<dyn Error>> {
+        let mut ledger = Ledger::new(Config::default());
+        ledger.deposit(1, 1, 10.0);
+        ledger.deposit(1, 2, 5.0);
+        ledger.deposit(1, 3, 1.0);
+        ledger.withdrawal(1, 4, 2.0);
+        // rejected transactions are not counted.
+        ledger.withdrawal(1, 5, 100.0);
+        ledger.dispute(1, 2);
+        ledger.resolve(1, 2);
+        ledger.dispute(1, 4);
+        ledger.deposit(2, 6, 3.0);
+        let mut out = Vec::new();
+        write_event_count_accounts(&mut out, ledger.accounts().values(), ledger.activities())?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "client, available, held, total, locked, deposits, withdrawals, disputes\n\
+             1,12.0000,2.0000,14.0000,false,3,1,2\n\
+             2,3.0000,0.0000,3.0000,false,1,0,0\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use crate::amount::Amount;
+
+/// Fixed size uniform sample of the transaction amounts, answering approximate percentiles.
+/// Every amount is kept until `capacity` is reached, the percentiles are exact up to there.
+#[derive(Debug, Clone)]
+pub(crate) struct AmountReservoir<A> {
+    capacity: usize,
+    seen: u64,
+    samples: Vec<A>,
+    /// xorshift state, seeded with a constant so runs are reproducible.
+    state: u64,
+}
+
+impl<A: Amount> AmountReservoir<A> {
+    pub(crate) fn new(capacity: usize) -> AmountReservoir<A> {
+        AmountReservoir {
+            capacity,
+            seen: 0,
+            samples: Vec::with_capacity(capacity),
+            state: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// Offer an amount, it replaces a random sample with probability `capacity / seen` once full.
+    pub(crate) fn insert(&mut self, amount: A) {
+        self.seen += 1;
+        if self.samples.len() < self.capacity {
+            self.samples.push(amount);
+            return;
+        }
+        let slot = (self.next_random() % self.seen) as usize;
+        if slot < self.capacity {
+            self.samples[slot] = amount;
+        }
+    }
+
+    /// Offer the samples of another reservoir, exact as long as both saw fewer amounts than fit.
+    pub(crate) fn extend(&mut self, other: AmountReservoir<A>) {
+        for amount in other.samples {
+            self.insert(amount);
+        }
+    }
+
+    /// Nearest-rank percentile of the sampled amounts, `p` ranges from 0 to 100.
+    pub(crate) fn percentile(&self, p: f64) -> Option<A> {
+        if self.samples.is_empty() || !(0.0..=100.0).contains(&p) {
+            return None;
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.saturating_sub(1)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reservoir_percentile() {
+        let mut exact = AmountReservoir::new(1000);
+        let mut sampled = AmountReservoir::new(200);
+        for amount in 1..=1000 {
+            exact.insert(amount as f32);
+            sampled.insert(amount as f32);
+        }
+        assert_eq!(exact.percentile(50.0), Some(500.0));
+        assert_eq!(exact.percentile(95.0), Some(950.0));
+        assert_eq!(exact.percentile(0.0), Some(1.0));
+        let p50 = sampled.percentile(50.0).unwrap();
+        let p95 = sampled.percentile(95.0).unwrap();
+        assert!((p50 - 500.0).abs() < 100.0, "p50 {}", p50);
+        assert!((p95 - 950.0).abs() < 50.0, "p95 {}", p95);
+        assert_eq!(sampled.percentile(101.0), None);
+    }
+}
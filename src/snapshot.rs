@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "csv")]
+use std::io;
+
+use crate::account::Account;
+#[cfg(feature = "csv")]
+use crate::error::LedgerError;
+
+/// Reads account balances previously written by `write_accounts`.
+#[cfg(feature = "csv")]
+pub fn load_snapshot<R: io::Read>(reader: R) -> Result<BTreeMap<u16, Account>, LedgerError> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut accounts = BTreeMap::new();
+    for result in rdr.deserialize() {
+        let account: Account = result?;
+        accounts.insert(account.client, account);
+    }
+    Ok(accounts)
+}
+
+/// Diff the ledger against a baseline, returning new accounts and accounts whose balances moved.
+pub fn changed_accounts<'a, A: PartialEq>(
+    baseline: &BTreeMap<u16, Account<A>>,
+    accounts: &'a BTreeMap<u16, Account<A>>,
+) -> Vec<&'a Account<A>> {
+    accounts
+        .values()
+        .filter(|account| {
+            !baseline
+                .get(&account.client)
+                .is_some_and(|previous| previous.same_balances(account))
+        })
+        .collect()
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::input::process_records;
+    use crate::ledger::{Config, Ledger};
+    use crate::output::write_accounts;
+
+    #[test]
+    fn test_changed_since_snapshot() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        let mut snapshot = Vec::new();
+        write_accounts(&mut snapshot, ledger.accounts().values())?;
+
+        let baseline = load_snapshot(snapshot.as_slice())?;
+        // the snapshot keeps every balance, only the lock reasons are not written.
+        assert_eq!(baseline.len(), ledger.accounts().len());
+        assert!(changed_accounts(&baseline, ledger.accounts()).is_empty());
+        assert!(baseline
+            .values()
+            .all(|account| account.lock_reason.is_none()));
+        let mut ledger = Ledger::with_accounts(baseline.clone(), Config::default());
+        ledger.process_file("src/tests/input/incremental.csv")?;
+
+        let mut changed: Vec<u16> = changed_accounts(&baseline, ledger.accounts())
+            .iter()
+            .map(|account| account.client)
+            .collect();
+        changed.sort();
+        assert_eq!(changed, vec![2, 5]);
+        assert_eq!(ledger[&2].total, 112.0);
+        assert_eq!(ledger[&5].total, 240.0);
+        Ok(())
+    }
+}
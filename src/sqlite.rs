@@ -0,0 +1,75 @@
+use rusqlite::{params, Connection};
+
+use crate::account::Account;
+use crate::error::LedgerError;
+
+/// Writes the accounts into the `accounts` table, creating it when needed.
+/// Rows of clients already present are replaced.
+pub fn write_sqlite<'a>(
+    conn: &mut Connection,
+    accounts: impl IntoIterator<Item = &'a Account>,
+) -> Result<(), LedgerError> {
+    let tx = conn.transaction()?;
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS accounts (
+            client INTEGER PRIMARY KEY,
+            available REAL NOT NULL,
+            held REAL NOT NULL,
+            total REAL NOT NULL,
+            locked INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    {
+        let mut insert = tx.prepare(
+            "INSERT OR REPLACE INTO accounts (client, available, held, total, locked)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for account in accounts {
+            insert.execute(params![
+                account.client,
+                account.available,
+                account.held,
+                account.total,
+                account.locked
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::input::process_records;
+    use crate::ledger::Config;
+
+    #[test]
+    fn test_write_sqlite() -> Result<(), Box<dyn Error>> {
+        let ledger = process_records("src/tests/input/mixed.csv", &Config::default())?;
+        let mut conn = Connection::open_in_memory()?;
+        write_sqlite(&mut conn, ledger.accounts().values())?;
+
+        let mut select = conn.prepare(
+            "SELECT client, available, held, total, locked FROM accounts ORDER BY client",
+        )?;
+        let rows: Vec<Account> = select
+            .query_map([], |row| {
+                Ok(Account {
+                    client: row.get(0)?,
+                    available: row.get(1)?,
+                    held: row.get(2)?,
+                    total: row.get(3)?,
+                    locked: row.get(4)?,
+                    lock_reason: None,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        let accounts: Vec<Account> = ledger.accounts().values().cloned().collect();
+        assert_eq!(rows, accounts);
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use crate::transaction::TxType;
+
+/// Counts of the transactions offered to the ledger, with a breakdown of the dispute cycles.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProcessingStats {
+    /// Transactions offered to `Ledger::apply` and adjustments offered to `Ledger::adjust`,
+    /// applied or not.
+    pub processed: usize,
+    pub applied: usize,
+    pub rejected: usize,
+    /// Applied disputes referencing a deposit.
+    pub disputed_deposits: usize,
+    /// Applied disputes referencing a withdrawal.
+    pub disputed_withdrawals: usize,
+    /// Disputes settled by a resolve.
+    pub resolved: usize,
+    /// Disputes settled by a chargeback.
+    pub charged_back: usize,
+}
+
+impl ProcessingStats {
+    /// Account for an applied transaction, `referenced` is the type of the transaction it disputes.
+    pub(crate) fn record_applied(&mut self, r#type: &TxType, referenced: Option<&TxType>) {
+        self.applied += 1;
+        match (r#type, referenced) {
+            (TxType::Dispute, Some(TxType::Deposit)) => self.disputed_deposits += 1,
+            (TxType::Dispute, Some(TxType::Withdrawal)) => self.disputed_withdrawals += 1,
+            (TxType::Resolve, _) => self.resolved += 1,
+            (TxType::Chargeback, _) => self.charged_back += 1,
+            _ => {}
+        }
+    }
+
+    /// Add the counts of another ledger.
+    pub(crate) fn merge(&mut self, other: &ProcessingStats) {
+        self.processed += other.processed;
+        self.applied += other.applied;
+        self.rejected += other.rejected;
+        self.disputed_deposits += other.disputed_deposits;
+        self.disputed_withdrawals += other.disputed_withdrawals;
+        self.resolved += other.resolved;
+        self.charged_back += other.charged_back;
+    }
+}
@@ -0,0 +1,64 @@
+use std::pin::pin;
+
+use futures_util::{Stream, StreamExt};
+
+use crate::amount::Amount;
+use crate::ledger::Ledger;
+use crate::transaction::Transaction;
+
+impl<A: Amount> Ledger<A> {
+    /// Applies transactions as an async stream yields them until it ends, returning how many were
+    /// applied. Only the input is async, each transaction is applied synchronously.
+    /// The transactions still deferred are flushed once the stream ends.
+    pub async fn process_stream<S: Stream<Item = Transaction<A>>>(&mut self, stream: S) -> usize {
+        let mut stream = pin!(stream);
+        let mut applied = 0;
+        while let Some(record) = stream.next().await {
+            if self.apply(record) {
+                applied += 1;
+            }
+        }
+        self.flush_deferred();
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+
+    use crate::ledger::{Config, Ledger};
+    use crate::transaction::{Transaction, TxType};
+
+    #[tokio::test]
+    async fn test_process_stream() {
+        let records = stream::iter(vec![
+            Transaction::new(TxType::Deposit, 1, 1, Some(10.0)),
+            Transaction::new(TxType::Deposit, 2, 2, Some(4.0)),
+            Transaction::new(TxType::Withdrawal, 1, 3, Some(2.5)),
+            Transaction::new(TxType::Withdrawal, 2, 4, Some(9.0)),
+            Transaction::new(TxType::Dispute, 2, 2, None),
+        ]);
+        let mut ledger = Ledger::new(Config::default());
+        assert_eq!(ledger.process_stream(records).await, 4);
+        assert_eq!(ledger[&1].available, 7.5);
+        assert_eq!(ledger[&1].total, 7.5);
+        assert_eq!(ledger[&2].available, 0.0);
+        assert_eq!(ledger[&2].held, 4.0);
+    }
+
+    #[tokio::test]
+    async fn test_process_stream_flushes_deferred() {
+        let records = stream::iter(vec![
+            Transaction::new(TxType::Deposit, 1, 1, Some(10.0)),
+            Transaction::new(TxType::Dispute, 1, 2, None),
+        ]);
+        let mut ledger = Ledger::new(Config {
+            defer_window: Some(5),
+            ..Config::default()
+        });
+        assert_eq!(ledger.process_stream(records).await, 1);
+        assert_eq!(ledger.processed(), 2);
+        assert_eq!(ledger.stats().rejected, 1);
+    }
+}
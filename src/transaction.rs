@@ -0,0 +1,83 @@
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::amount::{deserialize_amount, Amount};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[derive(PartialEq)]
+pub enum TxType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    /// Any type this version does not know, i.e `freeze` from a newer producer.
+    /// Never applied, skipped in lenient mode and refused otherwise.
+    Unknown,
+}
+
+/// Unknown names read as `Unknown`, a blank type is malformed rather than unknown.
+impl<'de> Deserialize<'de> for TxType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        match name.as_str() {
+            "deposit" => Ok(TxType::Deposit),
+            "withdrawal" => Ok(TxType::Withdrawal),
+            "dispute" => Ok(TxType::Dispute),
+            "resolve" => Ok(TxType::Resolve),
+            "chargeback" => Ok(TxType::Chargeback),
+            "" => Err(serde::de::Error::custom("missing transaction type")),
+            _ => Ok(TxType::Unknown),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "A: Amount"))]
+/// Represents incoming transaction from csv.
+pub struct Transaction<A = f32> {
+    pub client: u16,
+    pub tx: u32,
+    #[serde(default, deserialize_with = "deserialize_amount")]
+    pub amount: Option<A>,
+    /// Optional withdrawal fee, deducted together with the amount.
+    #[serde(default, deserialize_with = "deserialize_amount")]
+    pub fee: Option<A>,
+    pub r#type: TxType,
+    /// Optional unix timestamp column.
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// Optional free-text memo, kept for auditing only.
+    #[serde(default)]
+    pub memo: Option<String>,
+    /// Optional upstream delivery key, a transaction whose key was already applied is a no-op.
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+/// Manual correction posted with `Ledger::adjust`, kept in its own log apart from the transactions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adjustment<A = f32> {
+    /// Sequence number of the `Ledger::adjust` call from 1, unrelated to the tx ids.
+    pub id: u32,
+    pub client: u16,
+    /// Signed amount credited to the available and total funds.
+    pub delta: A,
+    /// Reason given for the correction.
+    pub note: String,
+}
+
+impl<A: Amount> Transaction<A> {
+    pub fn new(r#type: TxType, client: u16, tx: u32, amount: Option<A>) -> Transaction<A> {
+        Transaction {
+            client,
+            tx,
+            amount,
+            fee: None,
+            r#type,
+            timestamp: None,
+            memo: None,
+            idempotency_key: None,
+        }
+    }
+}